@@ -1,8 +1,12 @@
-use std::io::prelude::*;
+use std::time::Duration;
 
 fn main() {
     // a poor man cli parser.
-    let mut args = std::env::args().skip(1);
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("bench") {
+        args.next();
+        return bench(args);
+    }
 
     let from: u32 = args
         .next()
@@ -13,44 +17,94 @@ fn main() {
         .map(|v| v.parse().unwrap_or_else(|_| print_help()))
         .unwrap_or_else(|| print_help());
 
+    let mut builder = ethash::builder::RootsBuilder::open("roots.txt")
+        .expect("Failed to read roots.txt file");
+    let epochs = from as usize..to as usize;
     println!(
-        "Starting to calculate DAG Roots from {} to {} (i.e {} epochs)",
+        "Starting to calculate DAG Roots from {} to {} (i.e {} epochs, {} \
+         already done)",
         from,
         to,
-        to.wrapping_sub(from)
+        to.wrapping_sub(from),
+        epochs.len() - builder.pending(epochs.clone()).len(),
     );
 
-    let mut roots = std::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .append(true)
-        .truncate(false)
-        .open("roots.txt")
-        .expect("Failed to create/open roots.txt file");
+    builder
+        .build(epochs, |epoch, root| {
+            println!(
+                "epoch {} dataset size: {} MB",
+                epoch,
+                ethash::get_full_size(epoch) / (1024 * 1024)
+            );
+            println!("{}:{:?}", epoch, root);
+        })
+        .expect("failed to save the root for the last epoch");
+}
+
+fn bench(mut args: impl Iterator<Item = String>) {
+    let mut config = ethash::bench::BenchConfig {
+        epoch: args
+            .next()
+            .map(|v| v.parse().unwrap_or_else(|_| print_help()))
+            .unwrap_or_else(|| print_help()),
+        ..Default::default()
+    };
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or_else(|| print_help())
+        };
+        match flag.as_str() {
+            "--full" => config.full = true,
+            "--merkle" => config.merkle = true,
+            "--seconds" => config.duration = Duration::from_secs(value()),
+            "--threads" => config.threads = value() as usize,
+            _ => print_help(),
+        }
+    }
 
-    for i in from..to {
-        let epoch = i as usize;
-        let dataset_size = ethash::get_full_size(epoch);
-        let mut dataset = vec![0u8; dataset_size];
+    let report = ethash::bench::run(&config);
+    let mb = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "epoch {}: cache {:.1} MB, dataset {:.1} MB, {} threads",
+        report.epoch,
+        mb(report.cache_size),
+        mb(report.full_size),
+        report.threads,
+    );
+    println!("make_cache: {:?}", report.make_cache);
+    println!(
+        "light: {:.1} H/s ({} hashes in {:?})",
+        report.light.rate(),
+        report.light.hashes,
+        report.light.elapsed,
+    );
+    if let Some(elapsed) = report.make_dataset {
+        println!("make_dataset: {:?}", elapsed);
+    }
+    if let Some(full) = report.full {
         println!(
-            "epoch {} dataset size: {} MB",
-            epoch,
-            dataset_size / (1024 * 1024)
+            "full: {:.1} H/s ({} hashes in {:?})",
+            full.rate(),
+            full.hashes,
+            full.elapsed,
         );
-
-        let cache_size = ethash::get_cache_size(epoch);
-        let seed = ethash::get_seedhash(epoch);
-        let mut cache = vec![0u8; cache_size];
-        ethash::make_cache(&mut cache, seed);
-        ethash::make_dataset(&mut dataset, &cache);
-        let root = ethash::calc_dataset_merkle_root(epoch, &dataset);
-        println!("{}:{:?}", epoch, root);
-        writeln!(roots, "{}:{:?}", epoch, root)
-            .expect("failed to save the root for the last epoch");
+    }
+    if let Some(elapsed) = report.merkle_root {
+        println!("merkle root: {:?}", elapsed);
+    }
+    match report.peak_rss {
+        Some(rss) => println!("peak RSS: {:.1} MB", mb(rss)),
+        None => println!("estimated buffers: {:.1} MB", mb(report.buffers)),
     }
 }
 
 fn print_help() -> ! {
     println!("usage: epoch <FROM_EPOCH> <TO_EPOCH>");
+    println!(
+        "       epoch bench <EPOCH> [--full] [--merkle] [--seconds <N>] \
+         [--threads <N>]"
+    );
     std::process::exit(1);
 }
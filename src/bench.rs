@@ -0,0 +1,333 @@
+//! Hashrate measurement.
+//!
+//! [`run`] times the stages of mining an epoch on the current machine:
+//! building the light cache and the full dataset, computing the dataset
+//! Merkle root, and hashing with either of them. [`HashrateMeter`] tracks
+//! the hashrate of a running miner over a sliding window.
+
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ethereum_types::{H256, U256};
+
+/// Hashrate over the last `window`, fed with the number of hashes of every
+/// batch a miner completes.
+#[derive(Debug, Clone)]
+pub struct HashrateMeter {
+    window: Duration,
+    started: Instant,
+    samples: VecDeque<(Instant, u64)>,
+    total: u64,
+}
+
+impl HashrateMeter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            started: Instant::now(),
+            samples: VecDeque::new(),
+            total: 0,
+        }
+    }
+
+    /// Record `hashes` completed now.
+    pub fn record(&mut self, hashes: u64) {
+        self.record_at(Instant::now(), hashes)
+    }
+
+    /// Record `hashes` completed at `at`, which is no earlier than the
+    /// previous sample.
+    pub fn record_at(&mut self, at: Instant, hashes: u64) {
+        self.samples.push_back((at, hashes));
+        self.total += hashes;
+        while let Some(&(first, _)) = self.samples.front() {
+            if at.duration_since(first) < self.window {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    /// Hashes per second over the last window.
+    pub fn hashrate(&self) -> f64 { self.hashrate_at(Instant::now()) }
+
+    /// Hashes per second over the window ending at `now`. A meter younger
+    /// than its window is averaged over its age.
+    pub fn hashrate_at(&self, now: Instant) -> f64 {
+        let span = now.saturating_duration_since(self.started).min(self.window);
+        if span.is_zero() {
+            return 0.0;
+        }
+        let hashes: u64 = self
+            .samples
+            .iter()
+            .filter(|(at, _)| now.saturating_duration_since(*at) < self.window)
+            .map(|(_, hashes)| hashes)
+            .sum();
+        hashes as f64 / span.as_secs_f64()
+    }
+
+    /// Hashes recorded since the meter was created.
+    pub fn total(&self) -> u64 { self.total }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchConfig {
+    pub epoch: usize,
+    /// Minimum time spent hashing in each mode.
+    pub duration: Duration,
+    /// Number of hashing threads.
+    pub threads: usize,
+    /// Build the full dataset and hash with it.
+    pub full: bool,
+    /// Compute the dataset Merkle root, from the dataset if it was built and
+    /// from the light cache otherwise.
+    #[cfg(feature = "withproofs")]
+    pub merkle: bool,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            epoch: 0,
+            duration: Duration::from_secs(5),
+            threads: num_cpus::get(),
+            full: false,
+            #[cfg(feature = "withproofs")]
+            merkle: false,
+        }
+    }
+}
+
+/// Hashes done by a hashing stage, and how long it took.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Hashrate {
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl Hashrate {
+    /// Hashes per second.
+    pub fn rate(&self) -> f64 {
+        self.hashes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub epoch: usize,
+    pub cache_size: usize,
+    pub full_size: usize,
+    pub threads: usize,
+    pub make_cache: Duration,
+    pub light: Hashrate,
+    pub make_dataset: Option<Duration>,
+    pub full: Option<Hashrate>,
+    pub merkle_root: Option<Duration>,
+    /// Estimated bytes of the buffers the run allocated: the cache, the
+    /// dataset and, when the Merkle root is computed from the dataset, the
+    /// leaves and levels of its tree.
+    pub buffers: usize,
+    /// Peak resident set size of the process in bytes, where the platform
+    /// reports it.
+    pub peak_rss: Option<usize>,
+}
+
+/// Run the stages selected by `config`.
+pub fn run(config: &BenchConfig) -> BenchReport {
+    let epoch = config.epoch;
+    let cache_size = crate::get_cache_size(epoch);
+    let full_size = crate::get_full_size(epoch);
+    let threads = config.threads.max(1);
+
+    let started = Instant::now();
+    let mut cache = vec![0u8; cache_size];
+    crate::make_cache(&mut cache, crate::get_seedhash(epoch));
+    let make_cache = started.elapsed();
+
+    let light = hashrate(config.duration, threads, |nonces| {
+        crate::search_light(HEADER, full_size, &cache, U256::zero(), nonces)
+            .hashes
+    });
+
+    let mut dataset = Vec::new();
+    let mut make_dataset = None;
+    let mut full = None;
+    if config.full {
+        let started = Instant::now();
+        dataset = vec![0u8; full_size];
+        crate::make_dataset(&mut dataset, &cache);
+        make_dataset = Some(started.elapsed());
+        full = Some(hashrate(config.duration, threads, |nonces| {
+            let boundary = U256::zero();
+            crate::search_full(HEADER, full_size, &dataset, boundary, nonces)
+                .hashes
+        }));
+    }
+
+    // The root from the cache streams its leaves, the one from the dataset
+    // holds the whole tree.
+    #[cfg(feature = "withproofs")]
+    let (merkle_root, tree) = match config.merkle {
+        false => (None, 0),
+        true => {
+            let started = Instant::now();
+            let tree = if dataset.is_empty() {
+                crate::light::calc_dataset_merkle_root_from_cache(
+                    epoch, &cache,
+                );
+                0
+            } else {
+                crate::calc_dataset_merkle_root(epoch, &dataset);
+                tree_buffers(epoch)
+            };
+            (Some(started.elapsed()), tree)
+        },
+    };
+    #[cfg(not(feature = "withproofs"))]
+    let (merkle_root, tree) = (None, 0);
+
+    BenchReport {
+        epoch,
+        cache_size,
+        full_size,
+        threads,
+        make_cache,
+        light,
+        make_dataset,
+        full,
+        merkle_root,
+        buffers: cache.len() + dataset.len() + tree,
+        peak_rss: peak_rss(),
+    }
+}
+
+/// Peak resident set size of the process, from the `VmHWM` line of
+/// `/proc/self/status`.
+#[cfg(target_os = "linux")]
+fn peak_rss() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find_map(|l| l.strip_prefix("VmHWM:"))?;
+    let kb = line.trim().strip_suffix("kB")?.trim().parse::<usize>().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_rss() -> Option<usize> {
+    None
+}
+
+/// Bytes of the dataset tree of `epoch` built by
+/// [`crate::calc_dataset_merkle_root`]: its leaves, the leaf hashes the
+/// first level is hashed from, and the node hashes of every level.
+#[cfg(feature = "withproofs")]
+fn tree_buffers(epoch: usize) -> usize {
+    use core::mem::size_of;
+    use crate::mtree::{DobuleLeaf, Hash};
+
+    let leaves = crate::get_full_size(epoch) / 128;
+    let mut nodes = 0;
+    let mut level = leaves;
+    for _ in 0..crate::calc_dataset_depth(epoch) {
+        level = level.div_ceil(2);
+        nodes += level;
+    }
+    leaves * (size_of::<DobuleLeaf>() + size_of::<Hash>())
+        + nodes * size_of::<Hash>()
+}
+
+// Largest nonce range of a single search.
+const MAX_BATCH: u64 = 1 << 24;
+
+// Header hash every stage hashes, with a zero boundary no nonce meets.
+const HEADER: H256 = H256([0x42; 32]);
+
+/// Hash with `threads` threads, each searching its own nonce ranges with
+/// `search`, until `duration` has passed. Ranges grow from a single nonce
+/// until one takes a tenth of `duration`, so that a slow epoch does not
+/// overshoot it by much and a fast one is not dominated by the timing.
+fn hashrate<F>(duration: Duration, threads: usize, search: F) -> Hashrate
+where
+    F: Fn(core::ops::RangeInclusive<u64>) -> u64 + Sync,
+{
+    let started = Instant::now();
+    let hashes: u64 = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads as u64)
+            .map(|index| {
+                let search = &search;
+                scope.spawn(move || {
+                    let mut hashes = 0;
+                    let mut batch = 1;
+                    let mut nonce = index << 48;
+                    while started.elapsed() < duration {
+                        let batch_started = Instant::now();
+                        hashes += search(nonce..=nonce + batch - 1);
+                        nonce += batch;
+                        if batch_started.elapsed() < duration / 10 {
+                            batch = (batch * 2).min(MAX_BATCH);
+                        }
+                    }
+                    hashes
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).sum()
+    });
+    Hashrate {
+        hashes,
+        elapsed: started.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meter_window() {
+        let mut meter = HashrateMeter::new(Duration::from_secs(10));
+        let start = meter.started;
+        let at = |secs| start + Duration::from_secs(secs);
+        meter.record_at(at(1), 100);
+        meter.record_at(at(2), 100);
+        assert_eq!(meter.hashrate_at(at(4)), 50.0);
+        meter.record_at(at(12), 300);
+        // the first sample left the window.
+        assert_eq!(meter.hashrate_at(at(12)), 30.0);
+        assert_eq!(meter.hashrate_at(at(30)), 0.0);
+        assert_eq!(meter.total(), 500);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn peak_rss_of_process() {
+        let buffer = vec![1u8; 1 << 20];
+        assert!(peak_rss().unwrap() >= buffer.len());
+    }
+
+    #[test]
+    fn hashrate_threads() {
+        let duration = Duration::from_millis(50);
+        let rate = hashrate(duration, 2, |nonces| {
+            nonces.end() - nonces.start() + 1
+        });
+        assert!(rate.elapsed >= duration);
+        assert!(rate.hashes >= 2);
+    }
+
+    #[cfg(feature = "withproofs")]
+    #[test]
+    fn tree_buffers_of_epoch() {
+        use core::mem::size_of;
+        use crate::mtree::{DobuleLeaf, Hash};
+
+        // a tree has about as many nodes as leaves.
+        let leaves = crate::get_full_size(0) / 128;
+        let depth = crate::calc_dataset_depth(0);
+        let per_leaf = size_of::<DobuleLeaf>() + 2 * size_of::<Hash>();
+        let buffers = tree_buffers(0);
+        assert!(buffers >= leaves * per_leaf - size_of::<Hash>());
+        assert!(buffers <= leaves * per_leaf + depth * size_of::<Hash>());
+    }
+}
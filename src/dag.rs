@@ -1,10 +1,28 @@
 use alloc::vec::Vec;
+use core::ops::RangeInclusive;
 use ethereum_types::{H256, H64, U256};
 
-const EPOCH_LENGTH: usize = 30_000;
+use crate::SearchResult;
+
+/// Number of blocks sharing the same cache and dataset.
+pub const EPOCH_LENGTH: usize = 30_000;
+/// Number of blocks per epoch of Ethereum Classic from ECIP-1099 on.
+pub const ECIP1099_EPOCH_LENGTH: usize = 60_000;
+
+/// Sizes and epoch of a [`LightDAG`], without the cache itself.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LightDAGMetadata {
+    pub epoch: usize,
+    pub cache_size: usize,
+    pub full_size: usize,
+}
 
 pub struct LightDAG {
     pub epoch: usize,
+    /// Blocks per epoch, [`EPOCH_LENGTH`] unless it was changed by a fork
+    /// such as ECIP-1099.
+    pub epoch_length: usize,
     pub cache: Vec<u8>,
     #[allow(dead_code)]
     pub cache_size: usize,
@@ -13,10 +31,17 @@ pub struct LightDAG {
 
 impl LightDAG {
     pub fn new(number: U256) -> Self {
-        let epoch = (number / EPOCH_LENGTH).as_usize();
+        Self::with_epoch_length(number, EPOCH_LENGTH)
+    }
+
+    /// The DAG of block `number` on a chain with epochs of `epoch_length`
+    /// blocks. Sizes follow the epoch number, while the seed hash still
+    /// follows the number of [`EPOCH_LENGTH`] blocks before the epoch.
+    pub fn with_epoch_length(number: U256, epoch_length: usize) -> Self {
+        let epoch = (number / epoch_length).as_usize();
         let cache_size = crate::get_cache_size(epoch);
         let full_size = crate::get_full_size(epoch);
-        let seed = crate::get_seedhash(epoch);
+        let seed = crate::get_seedhash(epoch * epoch_length / EPOCH_LENGTH);
 
         let mut cache: Vec<u8> = alloc::vec![0; cache_size];
         crate::make_cache(&mut cache, seed);
@@ -26,15 +51,41 @@ impl LightDAG {
             cache_size,
             full_size,
             epoch,
+            epoch_length,
         }
     }
 
+    /// Index of the seed hash of the DAG, see [`crate::get_seedhash`]. It
+    /// tells the DAGs of different epoch lengths apart.
+    pub fn seed_epoch(&self) -> usize {
+        self.epoch * self.epoch_length / EPOCH_LENGTH
+    }
+
     pub fn hashimoto(&self, hash: H256, nonce: H64) -> (H256, H256) {
         crate::hashimoto_light(hash, nonce, self.full_size, &self.cache)
     }
 
+    /// Search `nonces` for a seal of `hash` meeting `boundary` with the
+    /// light cache.
+    pub fn search(
+        &self,
+        hash: H256,
+        boundary: U256,
+        nonces: RangeInclusive<u64>,
+    ) -> SearchResult {
+        crate::search_light(hash, self.full_size, &self.cache, boundary, nonces)
+    }
+
+    pub fn metadata(&self) -> LightDAGMetadata {
+        LightDAGMetadata {
+            epoch: self.epoch,
+            cache_size: self.cache_size,
+            full_size: self.full_size,
+        }
+    }
+
     pub fn is_valid_for(&self, number: U256) -> bool {
-        (number / EPOCH_LENGTH).as_usize() == self.epoch
+        (number / self.epoch_length).as_usize() == self.epoch
     }
 
     pub fn from_cache(cache: Vec<u8>, number: U256) -> Self {
@@ -47,6 +98,22 @@ impl LightDAG {
             cache_size,
             full_size,
             epoch,
+            epoch_length: EPOCH_LENGTH,
+        }
+    }
+
+    /// A DAG of `epoch` with a cache of 31 items and a dataset of 128, cheap
+    /// to build and to mine with in tests.
+    #[cfg(test)]
+    pub(crate) fn tiny(epoch: usize) -> Self {
+        let mut cache = alloc::vec![0u8; 64 * 31];
+        crate::make_cache(&mut cache, crate::get_seedhash(epoch));
+        Self {
+            epoch,
+            epoch_length: EPOCH_LENGTH,
+            cache_size: cache.len(),
+            cache,
+            full_size: 128 * 32,
         }
     }
 }
@@ -5,16 +5,22 @@ extern crate alloc;
 
 // The reference algorithm used is from https://github.com/ethereum/wiki/wiki/Ethash
 
+#[cfg(feature = "std")]
+pub mod bench;
 mod dag;
 mod miller_rabin;
 #[cfg(feature = "withproofs")]
 mod proof;
 #[cfg(feature = "withproofs")]
 pub use proof::*;
+#[cfg(feature = "stratum")]
+pub mod stratum;
 
-pub use dag::{LightDAG};
+pub use dag::{
+    LightDAG, LightDAGMetadata, ECIP1099_EPOCH_LENGTH, EPOCH_LENGTH,
+};
 
-use core::ops::BitXor;
+use core::ops::{BitXor, RangeInclusive};
 
 use byteorder::{ByteOrder, LittleEndian};
 use ethereum_types::{BigEndianHash, H256, H512, H64, U256, U64};
@@ -74,7 +80,7 @@ fn fill_sha256(input: &[u8], a: &mut [u8], from_index: usize) {
 
 /// Make an Ethash cache using the given seed.
 pub fn make_cache(cache: &mut [u8], seed: H256) {
-    assert!(cache.len() % HASH_BYTES == 0);
+    assert!(cache.len().is_multiple_of(HASH_BYTES));
     let n = cache.len() / HASH_BYTES;
 
     fill_sha512(&seed[..], cache, 0);
@@ -147,7 +153,7 @@ fn fnv128(a: [u8; 128], b: [u8; 128]) -> [u8; 128] {
 
 /// Calculate the dataset item.
 pub fn calc_dataset_item(cache: &[u8], i: usize) -> H512 {
-    debug_assert!(cache.len() % 64 == 0);
+    debug_assert!(cache.len().is_multiple_of(64));
 
     let n = cache.len() / 64;
     let r = HASH_BYTES / WORD_BYTES;
@@ -164,7 +170,7 @@ pub fn calc_dataset_item(cache: &[u8], i: usize) -> H512 {
     }
     for j in 0..DATASET_PARENTS {
         let cache_index = fnv(
-            (i.bitxor(j) & (u32::max_value() as usize)) as u32,
+            (i.bitxor(j) & (u32::MAX as usize)) as u32,
             LittleEndian::read_u32(&mix[(j % r * 4)..]),
         ) as usize;
         let mut item = [0u8; 64];
@@ -232,23 +238,27 @@ pub fn hashimoto<F: Fn(usize) -> H512>(
         nonce,
         full_size,
         lookup,
-        |data| {
-            let mut hasher = Keccak256::default();
-            hasher.update(&data);
-            let mut res = [0u8; 32];
-            res.copy_from_slice(hasher.finalize().as_slice());
-            res
-        },
-        |data| {
-            let mut hasher = Keccak512::default();
-            hasher.update(&data);
-            let mut res = [0u8; 64];
-            res.copy_from_slice(hasher.finalize().as_slice());
-            res
-        },
+        keccak256,
+        keccak512,
     )
 }
 
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::default();
+    hasher.update(data);
+    let mut res = [0u8; 32];
+    res.copy_from_slice(hasher.finalize().as_slice());
+    res
+}
+
+fn keccak512(data: &[u8]) -> [u8; 64] {
+    let mut hasher = Keccak512::default();
+    hasher.update(data);
+    let mut res = [0u8; 64];
+    res.copy_from_slice(hasher.finalize().as_slice());
+    res
+}
+
 pub fn hashimoto_with_hasher<
     F: Fn(usize) -> H512,
     HF256: Fn(&[u8]) -> [u8; 32],
@@ -264,13 +274,7 @@ pub fn hashimoto_with_hasher<
     let n = full_size / HASH_BYTES;
     let w = MIX_BYTES / WORD_BYTES;
     const MIXHASHES: usize = MIX_BYTES / HASH_BYTES;
-    let s = {
-        let mut data = [0u8; 40];
-        data[..32].copy_from_slice(&header_hash.0);
-        data[32..].copy_from_slice(&nonce.0);
-        data[32..].reverse();
-        hasher512(&data)
-    };
+    let s = seed_hash(header_hash, nonce, &hasher512);
     let mut mix = [0u8; MIX_BYTES];
     for i in 0..MIXHASHES {
         for j in 0..64 {
@@ -306,15 +310,64 @@ pub fn hashimoto_with_hasher<
 
         LittleEndian::write_u32(&mut cmix[j..], c);
     }
-    let result = {
-        let mut data = [0u8; 64 + MIX_BYTES / 4];
-        data[..64].copy_from_slice(&s);
-        data[64..].copy_from_slice(&cmix);
-        hasher256(&data)
-    };
+    let result = final_hash(&s, &cmix, &hasher256);
     (H256::from(cmix), H256::from(result))
 }
 
+/// The final hash of a seal recomputed from its claimed mix digest, without
+/// accessing the dataset.
+///
+/// It only equals the result of [`hashimoto`] if `mix_hash` is the right one
+/// for the nonce, but a seal whose quick result is above its boundary is
+/// invalid either way, so this is a cheap filter in front of the full check.
+pub fn quick_hashimoto(header_hash: H256, nonce: H64, mix_hash: H256) -> H256 {
+    quick_hashimoto_with_hasher(
+        header_hash,
+        nonce,
+        mix_hash,
+        keccak256,
+        keccak512,
+    )
+}
+
+pub fn quick_hashimoto_with_hasher<
+    HF256: Fn(&[u8]) -> [u8; 32],
+    HF512: Fn(&[u8]) -> [u8; 64],
+>(
+    header_hash: H256,
+    nonce: H64,
+    mix_hash: H256,
+    hasher256: HF256,
+    hasher512: HF512,
+) -> H256 {
+    let s = seed_hash(header_hash, nonce, &hasher512);
+    H256::from(final_hash(&s, &mix_hash.0, &hasher256))
+}
+
+// `s` of the specification, seeding the mix.
+fn seed_hash<HF512: Fn(&[u8]) -> [u8; 64]>(
+    header_hash: H256,
+    nonce: H64,
+    hasher512: &HF512,
+) -> [u8; 64] {
+    let mut data = [0u8; 40];
+    data[..32].copy_from_slice(&header_hash.0);
+    data[32..].copy_from_slice(&nonce.0);
+    data[32..].reverse();
+    hasher512(&data)
+}
+
+fn final_hash<HF256: Fn(&[u8]) -> [u8; 32]>(
+    s: &[u8; 64],
+    cmix: &[u8; MIX_BYTES / 4],
+    hasher256: &HF256,
+) -> [u8; 32] {
+    let mut data = [0u8; 64 + MIX_BYTES / 4];
+    data[..64].copy_from_slice(s);
+    data[64..].copy_from_slice(cmix);
+    hasher256(&data)
+}
+
 /// Ethash used by a light client. Only stores the 16MB cache rather than the
 /// full dataset.
 pub fn hashimoto_light(
@@ -335,13 +388,7 @@ pub fn hashimoto_full(
     full_size: usize,
     dataset: &[u8],
 ) -> (H256, H256) {
-    hashimoto(header_hash, nonce, full_size, |i| {
-        let mut r = [0u8; 64];
-        for j in 0..64 {
-            r[j] = dataset[i * 64 + j];
-        }
-        H512::from(r)
-    })
+    hashimoto(header_hash, nonce, full_size, |i| dataset_item(dataset, i))
 }
 
 /// Convert across boundary. `f(x) = 2 ^ 256 / x`.
@@ -362,32 +409,100 @@ pub fn mine<T: Encodable>(
     nonce_start: H64,
     difficulty: U256,
 ) -> (H64, H256) {
-    let target = cross_boundary(difficulty);
-    let header = rlp::encode(header).to_vec();
-
-    let mut nonce_current = nonce_start;
-    loop {
-        let (_, result) = hashimoto(
-            H256::from_slice(Keccak256::digest(&header).as_slice()),
-            nonce_current,
-            full_size,
-            |i| {
-                let mut r = [0u8; 64];
-                for j in 0..64 {
-                    r[j] = dataset[i * 64 + j];
-                }
-                H512::from(r)
-            },
-        );
-        let result_cmp: U256 = result.into_uint();
-        if result_cmp <= target {
-            return (nonce_current, result);
+    let header_hash =
+        H256::from_slice(Keccak256::digest(rlp::encode(header)).as_slice());
+    let start = nonce_start.into_uint().as_u64();
+    let boundary = cross_boundary(difficulty);
+    let search = |nonces| {
+        search_full(header_hash, full_size, dataset, boundary, nonces).solution
+    };
+    // from the start nonce up, then wrapping around to the nonces below it.
+    let solution = search(start..=u64::MAX)
+        .or_else(|| start.checked_sub(1).and_then(|end| search(0..=end)))
+        .expect("a nonce is found before the nonces run out");
+    (solution.nonce, solution.hash)
+}
+
+/// A nonce sealing a header, with its mix hash and final hash.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Solution {
+    pub nonce: H64,
+    pub mix_hash: H256,
+    pub hash: H256,
+}
+
+/// Outcome of a nonce search.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    /// The first nonce of the range meeting the boundary, if any.
+    pub solution: Option<Solution>,
+    /// Number of nonces hashed.
+    pub hashes: u64,
+}
+
+/// Search `nonces` in order for the first one whose final hash is at most
+/// `boundary`, given the seal hash of the header and a dataset lookup.
+///
+/// The boundary of a difficulty is [`cross_boundary`] of it.
+pub fn search<F: Fn(usize) -> H512>(
+    header_hash: H256,
+    full_size: usize,
+    lookup: F,
+    boundary: U256,
+    nonces: RangeInclusive<u64>,
+) -> SearchResult {
+    let mut hashes = 0;
+    for nonce in nonces {
+        let nonce = H64::from_uint(&U64::from(nonce));
+        let (mix_hash, hash) =
+            hashimoto(header_hash, nonce, full_size, &lookup);
+        hashes += 1;
+        if hash.into_uint() <= boundary {
+            let solution = Solution {
+                nonce,
+                mix_hash,
+                hash,
+            };
+            return SearchResult {
+                solution: Some(solution),
+                hashes,
+            };
         }
-        let nonce_u64 = nonce_current.into_uint().as_u64();
-        nonce_current = H64::from_uint(&U64::from(nonce_u64 + 1));
+    }
+    SearchResult {
+        solution: None,
+        hashes,
     }
 }
 
+/// [`search`] with the light cache.
+pub fn search_light(
+    header_hash: H256,
+    full_size: usize,
+    cache: &[u8],
+    boundary: U256,
+    nonces: RangeInclusive<u64>,
+) -> SearchResult {
+    let lookup = |i| calc_dataset_item(cache, i);
+    search(header_hash, full_size, lookup, boundary, nonces)
+}
+
+/// [`search`] with the full dataset.
+pub fn search_full(
+    header_hash: H256,
+    full_size: usize,
+    dataset: &[u8],
+    boundary: U256,
+    nonces: RangeInclusive<u64>,
+) -> SearchResult {
+    let lookup = |i| dataset_item(dataset, i);
+    search(header_hash, full_size, lookup, boundary, nonces)
+}
+
+fn dataset_item(dataset: &[u8], i: usize) -> H512 {
+    H512::from_slice(&dataset[i * HASH_BYTES..(i + 1) * HASH_BYTES])
+}
+
 /// Get the seedhash for a given block number.
 pub fn get_seedhash(epoch: usize) -> H256 {
     let mut s = [0u8; 32];
@@ -397,10 +512,23 @@ pub fn get_seedhash(epoch: usize) -> H256 {
     H256::from_slice(s.as_ref())
 }
 
+/// Get the epoch of a seedhash, looking among the first `max_epochs` epochs.
+pub fn get_epoch_from_seedhash(seed: H256, max_epochs: usize) -> Option<usize> {
+    let mut s = [0u8; 32];
+    for epoch in 0..max_epochs {
+        if s == seed.0 {
+            return Some(epoch);
+        }
+        fill_sha256(&s.clone(), &mut s, 0);
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{LightDAG};
-    use ethereum_types::{H256, H64};
+    use ethereum_types::{BigEndianHash, H256, H64, U256};
+    use sha3::{Digest, Keccak256};
 
     #[test]
     fn hashimoto_should_work() {
@@ -422,4 +550,84 @@ mod tests {
             ).unwrap())
         );
     }
+
+    #[test]
+    fn epoch_from_seedhash() {
+        let seed = crate::get_seedhash(5);
+        assert_eq!(crate::get_epoch_from_seedhash(seed, 10), Some(5));
+        assert_eq!(crate::get_epoch_from_seedhash(seed, 5), None);
+        assert_eq!(crate::get_epoch_from_seedhash(H256::zero(), 1), Some(0));
+    }
+
+    #[test]
+    fn search_ranges() {
+        let mut cache = vec![0u8; 64 * 31];
+        crate::make_cache(&mut cache, H256::zero());
+        let full_size = 128 * 32;
+        let mut dataset = vec![0u8; full_size];
+        crate::make_dataset(&mut dataset, &cache);
+        let header_hash = H256::repeat_byte(7);
+        let boundary = crate::cross_boundary(U256::from(8));
+
+        let nonces = 5..=1000;
+        let full = crate::search_full(
+            header_hash,
+            full_size,
+            &dataset,
+            boundary,
+            nonces.clone(),
+        );
+        let light = crate::search_light(
+            header_hash,
+            full_size,
+            &cache,
+            boundary,
+            nonces,
+        );
+        assert_eq!(full, light);
+        let solution = full.solution.unwrap();
+        let nonce = solution.nonce.to_low_u64_be();
+        assert_eq!(full.hashes, nonce - 4);
+        assert!(solution.hash.into_uint() <= boundary);
+        let nonce = solution.nonce;
+        assert_eq!(
+            crate::hashimoto_full(header_hash, nonce, full_size, &dataset),
+            (solution.mix_hash, solution.hash),
+        );
+
+        let none = crate::search_full(
+            header_hash,
+            full_size,
+            &dataset,
+            U256::zero(),
+            10..=19,
+        );
+        assert_eq!(none.solution, None);
+        assert_eq!(none.hashes, 10);
+
+        // the last nonce is searched too.
+        let last = crate::search_full(
+            header_hash,
+            full_size,
+            &dataset,
+            U256::MAX,
+            u64::MAX..=u64::MAX,
+        );
+        assert_eq!(last.solution.unwrap().nonce, H64::repeat_byte(0xff));
+        let start = H64::repeat_byte(0xff);
+        let (nonce, _) =
+            crate::mine(&1u64, full_size, &dataset, start, U256::one());
+        assert_eq!(nonce, start);
+        // and mining wraps around to the nonces below the start, here with
+        // a difficulty the last nonce misses.
+        let seal_hash =
+            H256::from_slice(&Keccak256::digest(rlp::encode(&1u64)));
+        let (_, last) =
+            crate::hashimoto_full(seal_hash, start, full_size, &dataset);
+        let difficulty = U256::MAX / last.into_uint() * 2 + 2;
+        let (nonce, hash) =
+            crate::mine(&1u64, full_size, &dataset, start, difficulty);
+        assert!(nonce < start);
+        assert!(hash.into_uint() <= crate::cross_boundary(difficulty));
+    }
 }
@@ -0,0 +1,206 @@
+//! Solidity ABI encoding of [`BlockWithProofs`].
+//!
+//! The payload is the ABI encoding of the argument tuple
+//! `(bytes header, uint256[] elements, bytes16[] merkleProofs)`: the RLP of
+//! the header, the four words of each of the 64 elements accessed by its
+//! seal (that is, 128 dataset items of 64 bytes), and the branches of the
+//! elements one after the other. The Merkle root is not part of the payload,
+//! the verifier knows it from the epoch.
+
+use alloc::vec::Vec;
+
+use ethereum_types::{H256, U256};
+
+use super::mtree::Hash;
+use super::types::BlockWithProofs;
+use super::HASH_LENGTH;
+
+const SLOT: usize = 32;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AbiError {
+    // Data ends before the value being read
+    TooShort,
+    // An offset or length does not fit in the data
+    InvalidOffset,
+    // A `bytes16` slot has non-zero padding
+    InvalidPadding,
+    // Number of words is not a multiple of four, or number of branch hashes
+    // is not a multiple of the number of elements
+    InvalidProofLength,
+}
+
+/// The 4-byte selector of a function given its canonical signature, e.g.
+/// `addBlockHeader(bytes,uint256[],bytes16[])`.
+pub fn selector(signature: &str) -> [u8; 4] {
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&super::keccak_256(signature.as_bytes())[..4]);
+    selector
+}
+
+impl BlockWithProofs {
+    /// ABI-encode the header, elements and branches.
+    pub fn to_abi(&self) -> Vec<u8> {
+        let header = encode_bytes(&self.header_rlp);
+        let elements = encode_array(self.elements.iter().map(|e| e.0));
+        let proofs = encode_array(self.merkle_proofs.iter().map(|h| {
+            let mut slot = [0u8; SLOT];
+            slot[..HASH_LENGTH].copy_from_slice(&h.0);
+            slot
+        }));
+
+        let mut out = Vec::with_capacity(
+            3 * SLOT + header.len() + elements.len() + proofs.len(),
+        );
+        let mut offset = 3 * SLOT;
+        for part in [&header, &elements] {
+            out.extend_from_slice(&word(offset));
+            offset += part.len();
+        }
+        out.extend_from_slice(&word(offset));
+        out.extend(header);
+        out.extend(elements);
+        out.extend(proofs);
+        out
+    }
+
+    /// Calldata of a call to the function with the given `signature`, whose
+    /// arguments are the payload of [`BlockWithProofs::to_abi`].
+    pub fn to_abi_call(&self, signature: &str) -> Vec<u8> {
+        let mut out = selector(signature).to_vec();
+        out.extend(self.to_abi());
+        out
+    }
+
+    /// Decode the payload of [`BlockWithProofs::to_abi`]. The root is not
+    /// part of the payload and has to be supplied.
+    pub fn from_abi(data: &[u8], merkle_root: Hash) -> Result<Self, AbiError> {
+        let header_rlp = read_bytes(data, read_offset(data, 0)?)?.to_vec();
+        let elements = read_array(data, read_offset(data, SLOT)?)?
+            .map(H256::from_slice)
+            .collect::<Vec<_>>();
+        let merkle_proofs = read_array(data, read_offset(data, 2 * SLOT)?)?
+            .map(|slot| {
+                if slot[HASH_LENGTH..].iter().any(|b| *b != 0) {
+                    return Err(AbiError::InvalidPadding);
+                }
+                Ok(Hash::from(&slot[..HASH_LENGTH]))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if elements.len() % 4 != 0 {
+            return Err(AbiError::InvalidProofLength);
+        }
+        let accesses = elements.len() / 4;
+        let proof_length = match accesses {
+            0 if merkle_proofs.is_empty() => 0,
+            0 => return Err(AbiError::InvalidProofLength),
+            _ if merkle_proofs.len() % accesses != 0 => {
+                return Err(AbiError::InvalidProofLength)
+            },
+            _ => merkle_proofs.len() / accesses,
+        };
+        Ok(Self {
+            proof_length: proof_length as u64,
+            header_rlp,
+            merkle_root,
+            elements,
+            merkle_proofs,
+        })
+    }
+}
+
+fn word(value: usize) -> [u8; SLOT] {
+    let mut slot = [0u8; SLOT];
+    U256::from(value).to_big_endian(&mut slot);
+    slot
+}
+
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let padded = bytes.len().div_ceil(SLOT) * SLOT;
+    let mut out = Vec::with_capacity(SLOT + padded);
+    out.extend_from_slice(&word(bytes.len()));
+    out.extend_from_slice(bytes);
+    out.resize(SLOT + padded, 0);
+    out
+}
+
+fn encode_array<I>(slots: I) -> Vec<u8>
+where
+    I: ExactSizeIterator<Item = [u8; SLOT]>,
+{
+    let mut out = Vec::with_capacity(SLOT * (slots.len() + 1));
+    out.extend_from_slice(&word(slots.len()));
+    slots.for_each(|slot| out.extend_from_slice(&slot));
+    out
+}
+
+fn read_slot(data: &[u8], at: usize) -> Result<&[u8], AbiError> {
+    data.get(at..at.checked_add(SLOT).ok_or(AbiError::InvalidOffset)?)
+        .ok_or(AbiError::TooShort)
+}
+
+fn read_offset(data: &[u8], at: usize) -> Result<usize, AbiError> {
+    let value = U256::from_big_endian(read_slot(data, at)?);
+    if value > U256::from(data.len()) {
+        return Err(AbiError::InvalidOffset);
+    }
+    Ok(value.as_usize())
+}
+
+fn read_bytes(data: &[u8], at: usize) -> Result<&[u8], AbiError> {
+    let len = read_offset(data, at)?;
+    let start = at + SLOT;
+    data.get(start..start + len).ok_or(AbiError::TooShort)
+}
+
+fn read_array(
+    data: &[u8],
+    at: usize,
+) -> Result<impl Iterator<Item = &[u8]>, AbiError> {
+    let len = read_offset(data, at)?;
+    let start = at + SLOT;
+    let items = data
+        .get(start..start + len * SLOT)
+        .ok_or(AbiError::TooShort)?;
+    Ok(items.chunks_exact(SLOT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let block = BlockWithProofs {
+            proof_length: 3,
+            header_rlp: (0..40).collect(),
+            merkle_root: Hash([9; HASH_LENGTH]),
+            elements: (0..8).map(H256::from_low_u64_be).collect(),
+            merkle_proofs: (0..6u8).map(|i| Hash([i; HASH_LENGTH])).collect(),
+        };
+        let data = block.to_abi();
+        // head, bytes (length + 2 slots), arrays (length + items)
+        assert_eq!(data.len(), SLOT * (3 + 3 + 9 + 7));
+        assert_eq!(&data[..SLOT], &word(3 * SLOT));
+        assert_eq!(
+            BlockWithProofs::from_abi(&data, block.merkle_root),
+            Ok(block.clone())
+        );
+        assert_eq!(
+            BlockWithProofs::from_abi(&data[..data.len() - 1], Hash::zero()),
+            Err(AbiError::TooShort)
+        );
+
+        let mut odd = block.clone();
+        odd.elements.push(H256::zero());
+        assert_eq!(
+            BlockWithProofs::from_abi(&odd.to_abi(), Hash::zero()),
+            Err(AbiError::InvalidProofLength)
+        );
+
+        let call = block.to_abi_call("transfer(address,uint256)");
+        assert_eq!(&call[..4], &[0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(&call[4..], &data[..]);
+    }
+}
@@ -0,0 +1,129 @@
+//! Resumable computation of the dataset Merkle roots of a range of epochs.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use ethereum_types::H128;
+
+use super::roots::EpochRoots;
+
+/// Computes the roots of a range of epochs into a roots file (see
+/// [`EpochRoots`] for the format).
+///
+/// Epochs already recorded in the file are skipped, so an interrupted run can
+/// simply be started again. The file is rewritten atomically after every new
+/// root. A single dataset buffer is reused across epochs, and the cache of
+/// the next epoch is generated on a separate thread while the current dataset
+/// is being generated and hashed.
+#[derive(Debug)]
+pub struct RootsBuilder {
+    path: PathBuf,
+    roots: EpochRoots,
+}
+
+impl RootsBuilder {
+    /// Open the roots file at `path`, loading the roots it already holds. A
+    /// missing file is treated as empty.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let roots = match fs::read_to_string(&path) {
+            Ok(s) => EpochRoots::parse(&s).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => EpochRoots::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, roots })
+    }
+
+    pub fn roots(&self) -> &EpochRoots { &self.roots }
+
+    pub fn into_roots(self) -> EpochRoots { self.roots }
+
+    /// Epochs of `epochs` that have no root recorded yet.
+    pub fn pending(&self, epochs: Range<usize>) -> Vec<usize> {
+        epochs.filter(|epoch| !self.roots.contains(*epoch)).collect()
+    }
+
+    /// Compute and record the root of every pending epoch in `epochs`,
+    /// calling `on_root` after each one has been saved.
+    pub fn build<F>(
+        &mut self,
+        epochs: Range<usize>,
+        mut on_root: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(usize, H128),
+    {
+        let pending = self.pending(epochs);
+        let mut dataset = Vec::new();
+        let mut cache = Vec::new();
+        let mut next_cache = Vec::new();
+        if let Some(&first) = pending.first() {
+            make_cache(&mut cache, first);
+        }
+
+        for (i, &epoch) in pending.iter().enumerate() {
+            dataset.resize(crate::get_full_size(epoch), 0);
+            let root = std::thread::scope(|s| {
+                if let Some(&next) = pending.get(i + 1) {
+                    let next_cache = &mut next_cache;
+                    s.spawn(move || make_cache(next_cache, next));
+                }
+                crate::make_dataset(&mut dataset, &cache);
+                super::calc_dataset_merkle_root(epoch, &dataset)
+            });
+            core::mem::swap(&mut cache, &mut next_cache);
+
+            self.roots.insert(epoch, root);
+            self.save()?;
+            on_root(epoch, root);
+        }
+        Ok(())
+    }
+
+    /// Atomically replace the roots file with the current table.
+    fn save(&self) -> io::Result<()> {
+        let mut tmp = OsString::from(self.path.as_os_str());
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(self.roots.to_lines().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn make_cache(cache: &mut Vec<u8>, epoch: usize) {
+    cache.resize(crate::get_cache_size(epoch), 0);
+    crate::make_cache(cache, crate::get_seedhash(epoch));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_recorded_epochs() {
+        let path = std::env::temp_dir()
+            .join(format!("ethash-roots-{}.txt", std::process::id()));
+        let mut roots = EpochRoots::new();
+        roots.insert(0, H128::from_low_u64_be(1));
+        roots.insert(2, H128::from_low_u64_be(3));
+        fs::write(&path, roots.to_lines()).unwrap();
+
+        let mut builder = RootsBuilder::open(&path).unwrap();
+        assert_eq!(builder.roots(), &roots);
+        assert_eq!(builder.pending(0..4), vec![1, 3]);
+
+        let mut computed = Vec::new();
+        builder.build(2..3, |epoch, _| computed.push(epoch)).unwrap();
+        assert!(computed.is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
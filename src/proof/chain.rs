@@ -0,0 +1,823 @@
+//! Header chain verification.
+//!
+//! [`validate_header`] checks a header against its parent: number, timestamp,
+//! gas limit, `extra_data` length, difficulty and, from London on, base fee
+//! (EIP-1559). Past the terminal total difficulty of the chain,
+//! [`validate_header_with_td`] applies the proof of stake rules instead. Under
+//! std, [`HeaderChain`]
+//! is the core of a PoW light client: it imports headers on top of a trusted
+//! checkpoint, checks them and their seals, keeps every branch along with its
+//! total difficulty and follows the heaviest one, reporting reorgs. With
+//! [`ChainConfig::ecbp1100`], reorgs also have to pass ECBP-1100.
+
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, HashMap};
+
+use core::cmp::Ordering;
+use core::ops::Range;
+
+use ethereum_types::U256;
+
+use super::difficulty::{calc_difficulty, DifficultyConfig};
+use super::types::BlockHeader;
+use super::uncles::uncles_hash;
+use crate::{LightDAG, ECIP1099_EPOCH_LENGTH, EPOCH_LENGTH};
+#[cfg(feature = "std")]
+use super::verify::{SealError, SealVerifier};
+#[cfg(feature = "std")]
+use super::weight::ecbp1100_allows;
+#[cfg(feature = "std")]
+use ethereum_types::H256;
+
+/// Base fee of the London fork block, in wei.
+pub const INITIAL_BASE_FEE: u64 = 1_000_000_000;
+/// Ratio of the gas limit to the gas target from London on.
+pub const ELASTICITY_MULTIPLIER: u64 = 2;
+const BASE_FEE_CHANGE_DENOMINATOR: u32 = 8;
+
+/// Consensus parameters of the headers of a chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ChainConfig {
+    pub difficulty: DifficultyConfig,
+    /// A block may change the gas limit of its parent by less than
+    /// `parent_gas_limit / gas_limit_bound_divisor`.
+    pub gas_limit_bound_divisor: u64,
+    pub min_gas_limit: u64,
+    pub max_extra_data: usize,
+    /// Blocks over which reorgs are subject to ECBP-1100 (MESS), see
+    /// [`super::weight`].
+    pub ecbp1100: Option<Range<u64>>,
+    /// From this block on headers carry a base fee (EIP-1559).
+    pub london_block: Option<u64>,
+    /// From this block on epochs last [`ECIP1099_EPOCH_LENGTH`] blocks.
+    pub ecip1099_block: Option<u64>,
+    /// Total difficulty at which the chain switched to proof of stake: the
+    /// children of blocks of at least this total difficulty are not sealed
+    /// by proof of work anymore.
+    pub terminal_total_difficulty: Option<U256>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            difficulty: DifficultyConfig::default(),
+            gas_limit_bound_divisor: 1024,
+            min_gas_limit: 5000,
+            max_extra_data: 32,
+            ecbp1100: None,
+            london_block: None,
+            ecip1099_block: None,
+            terminal_total_difficulty: None,
+        }
+    }
+}
+
+impl ChainConfig {
+    pub fn is_london(&self, number: U256) -> bool {
+        self.london_block.is_some_and(|block| number >= block.into())
+    }
+
+    /// Length of the epoch of block `number`.
+    pub fn epoch_length(&self, number: u64) -> usize {
+        match self.ecip1099_block {
+            Some(block) if number >= block => ECIP1099_EPOCH_LENGTH,
+            _ => EPOCH_LENGTH,
+        }
+    }
+
+    /// Epoch of block `number`.
+    pub fn epoch(&self, number: u64) -> usize {
+        number as usize / self.epoch_length(number)
+    }
+
+    /// Index of the seed hash of the epoch of block `number`, see
+    /// [`crate::get_seedhash`]. Unlike epochs, it tells epochs of different
+    /// lengths apart.
+    pub fn seed_epoch(&self, number: u64) -> usize {
+        self.epoch(number) * self.epoch_length(number) / EPOCH_LENGTH
+    }
+
+    /// Whether the children of a block of `total_difficulty` are proof of
+    /// stake headers.
+    pub fn is_merged(&self, total_difficulty: U256) -> bool {
+        self.terminal_total_difficulty
+            .is_some_and(|terminal| total_difficulty >= terminal)
+    }
+
+    /// The DAG to verify the seal of block `number` with.
+    pub fn light_dag(&self, number: u64) -> LightDAG {
+        LightDAG::with_epoch_length(number.into(), self.epoch_length(number))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum HeaderError {
+    // Number is not the one of the parent plus one
+    InvalidNumber,
+    // Timestamp is not after the one of the parent
+    InvalidTimestamp,
+    // Gas limit is out of the bounds set by the parent
+    InvalidGasLimit,
+    // More gas is used than the gas limit
+    InvalidGasUsed,
+    // `extra_data` is longer than allowed (length)
+    ExtraDataTooLong(usize),
+    // Difficulty is not the computed one (expected)
+    InvalidDifficulty(U256),
+    // Base fee is not the computed one, or is missing or unexpected
+    // (expected)
+    InvalidBaseFee(Option<U256>),
+    // Header is sealed by proof of work past the terminal total difficulty
+    PowAfterMerge,
+    // Nonce of a proof of stake header is not zero
+    NonZeroNonce,
+    // Proof of stake header includes uncles
+    NonEmptyUncles,
+    // Parent reached the terminal total difficulty, past which the chain is
+    // not followed by weight anymore
+    #[cfg(feature = "std")]
+    PastMerge,
+    // Parent of the header is unknown
+    #[cfg(feature = "std")]
+    UnknownParent,
+    // Seal is invalid
+    #[cfg(feature = "std")]
+    InvalidSeal(SealError),
+}
+
+/// Check `header` against its `parent`, except for its seal, as a proof of
+/// work header. Across the Merge, use [`validate_header_with_td`].
+pub fn validate_header(
+    config: &ChainConfig,
+    parent: &BlockHeader,
+    header: &BlockHeader,
+) -> Result<(), HeaderError> {
+    validate_fields(config, parent, header)?;
+    let expected =
+        calc_difficulty(&config.difficulty, parent, header.timestamp);
+    if header.difficulty != expected {
+        return Err(HeaderError::InvalidDifficulty(expected));
+    }
+    Ok(())
+}
+
+/// Check `header` against its `parent`, of `parent_total_difficulty`, except
+/// for its seal. Past [`ChainConfig::terminal_total_difficulty`] the header
+/// must follow the proof of stake rules: zero difficulty, zero nonce and no
+/// uncles.
+pub fn validate_header_with_td(
+    config: &ChainConfig,
+    parent: &BlockHeader,
+    parent_total_difficulty: U256,
+    header: &BlockHeader,
+) -> Result<(), HeaderError> {
+    if !config.is_merged(parent_total_difficulty) {
+        return validate_header(config, parent, header);
+    }
+    validate_fields(config, parent, header)?;
+    if !header.difficulty.is_zero() {
+        return Err(HeaderError::PowAfterMerge);
+    }
+    if !header.nonce.is_zero() {
+        return Err(HeaderError::NonZeroNonce);
+    }
+    if header.uncles_hash != uncles_hash(&[]) {
+        return Err(HeaderError::NonEmptyUncles);
+    }
+    Ok(())
+}
+
+/// The checks of the fields common to proof of work and proof of stake.
+fn validate_fields(
+    config: &ChainConfig,
+    parent: &BlockHeader,
+    header: &BlockHeader,
+) -> Result<(), HeaderError> {
+    if header.number != parent.number + 1 {
+        return Err(HeaderError::InvalidNumber);
+    }
+    if header.timestamp <= parent.timestamp {
+        return Err(HeaderError::InvalidTimestamp);
+    }
+    // the gas target of the fork block is the gas limit of its parent.
+    let parent_gas_limit =
+        if config.is_london(header.number) && !config.is_london(parent.number) {
+            parent.gas_limit * ELASTICITY_MULTIPLIER
+        } else {
+            parent.gas_limit
+        };
+    let bound = parent_gas_limit / config.gas_limit_bound_divisor;
+    if header.gas_limit.abs_diff(parent_gas_limit) >= bound
+        || header.gas_limit < config.min_gas_limit
+    {
+        return Err(HeaderError::InvalidGasLimit);
+    }
+    if header.gas_used > header.gas_limit {
+        return Err(HeaderError::InvalidGasUsed);
+    }
+    if header.extra_data.len() > config.max_extra_data {
+        let len = header.extra_data.len();
+        return Err(HeaderError::ExtraDataTooLong(len));
+    }
+    let expected = calc_base_fee(config, parent, header.number);
+    if header.base_fee_per_gas != expected {
+        return Err(HeaderError::InvalidBaseFee(expected));
+    }
+    Ok(())
+}
+
+/// The base fee of block `number`, a child of `parent`, or `None` before
+/// London. It starts at [`INITIAL_BASE_FEE`] and moves by up to 1/8 towards
+/// keeping blocks half full.
+pub fn calc_base_fee(
+    config: &ChainConfig,
+    parent: &BlockHeader,
+    number: U256,
+) -> Option<U256> {
+    if !config.is_london(number) {
+        return None;
+    }
+    let base_fee = match parent.base_fee_per_gas {
+        Some(base_fee) if config.is_london(parent.number) => base_fee,
+        _ => return Some(INITIAL_BASE_FEE.into()),
+    };
+    let target = parent.gas_limit / ELASTICITY_MULTIPLIER;
+    let change = |gas: u64| {
+        base_fee.saturating_mul(gas.into())
+            / target.max(1)
+            / BASE_FEE_CHANGE_DENOMINATOR
+    };
+    Some(match parent.gas_used.cmp(&target) {
+        Ordering::Equal => base_fee,
+        Ordering::Greater => base_fee.saturating_add(
+            change(parent.gas_used - target).max(U256::one()),
+        ),
+        Ordering::Less => {
+            base_fee.saturating_sub(change(target - parent.gas_used))
+        },
+    })
+}
+
+/// Outcome of [`HeaderChain::import`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Imported {
+    // The header was already imported
+    Known,
+    // The header extends a branch lighter than the canonical one
+    SideChain,
+    // The header is the new head
+    NewHead(HeadChange),
+}
+
+/// A change of the canonical head.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadChange {
+    pub old_head: H256,
+    pub new_head: H256,
+    /// Last block the old and new canonical chains have in common.
+    pub common_ancestor: H256,
+    /// Number of blocks of the old canonical chain that are no longer, 0 if
+    /// the new head extends the old one.
+    pub reorg_depth: u64,
+}
+
+#[cfg(feature = "std")]
+struct Entry {
+    header: BlockHeader,
+    total_difficulty: U256,
+}
+
+/// Every branch of headers imported on top of a checkpoint, and the
+/// canonical chain, i.e. the branch of highest total difficulty. Ties keep
+/// the branch seen first.
+#[cfg(feature = "std")]
+pub struct HeaderChain {
+    config: ChainConfig,
+    verifier: SealVerifier,
+    headers: HashMap<H256, Entry>,
+    canonical: BTreeMap<U256, H256>,
+    head: H256,
+}
+
+#[cfg(feature = "std")]
+impl HeaderChain {
+    /// A chain starting at the trusted `checkpoint`, whose total difficulty
+    /// (including itself) is `total_difficulty`.
+    pub fn new(
+        config: ChainConfig,
+        checkpoint: BlockHeader,
+        total_difficulty: U256,
+    ) -> Self {
+        let hash = checkpoint.hash();
+        let mut canonical = BTreeMap::new();
+        canonical.insert(checkpoint.number, hash);
+        let mut headers = HashMap::new();
+        headers.insert(
+            hash,
+            Entry {
+                header: checkpoint,
+                total_difficulty,
+            },
+        );
+        let mut verifier = SealVerifier::default();
+        verifier.set_ecip1099_block(config.ecip1099_block);
+        Self {
+            config,
+            verifier,
+            headers,
+            canonical,
+            head: hash,
+        }
+    }
+
+    /// Verify seals of the headers of `dag`'s epoch with it.
+    pub fn insert_dag(&mut self, dag: LightDAG) {
+        self.verifier.insert_dag(dag)
+    }
+
+    pub fn config(&self) -> &ChainConfig { &self.config }
+
+    pub fn head(&self) -> &BlockHeader { &self.headers[&self.head].header }
+
+    pub fn head_hash(&self) -> H256 { self.head }
+
+    pub fn header(&self, hash: H256) -> Option<&BlockHeader> {
+        self.headers.get(&hash).map(|entry| &entry.header)
+    }
+
+    pub fn total_difficulty(&self, hash: H256) -> Option<U256> {
+        self.headers.get(&hash).map(|entry| entry.total_difficulty)
+    }
+
+    /// Hash of the canonical block at `number`.
+    pub fn canonical_hash(&self, number: U256) -> Option<H256> {
+        self.canonical.get(&number).copied()
+    }
+
+    /// Check and import `header`.
+    pub fn import(
+        &mut self,
+        header: BlockHeader,
+    ) -> Result<Imported, HeaderError> {
+        if self.check(&header)? {
+            return Ok(Imported::Known);
+        }
+        let report = self.verifier.verify(core::slice::from_ref(&header));
+        if let Some((_, error)) = report.first_failure() {
+            return Err(HeaderError::InvalidSeal(error.clone()));
+        }
+        Ok(self.insert(header))
+    }
+
+    /// Import `headers` in order, checking them against their parents
+    /// first, earlier headers of the batch included, then verifying the
+    /// seals of the ones passing in parallel. Stops at the first invalid
+    /// header, returning its index.
+    pub fn import_all(
+        &mut self,
+        headers: Vec<BlockHeader>,
+    ) -> Result<Vec<Imported>, (usize, HeaderError)> {
+        // new headers of the batch by hash, with their index and total
+        // difficulty.
+        let mut batch: HashMap<H256, (usize, U256)> = HashMap::new();
+        let mut known = Vec::with_capacity(headers.len());
+        let mut failure = None;
+        for (i, header) in headers.iter().enumerate() {
+            let hash = header.hash();
+            if self.headers.contains_key(&hash) || batch.contains_key(&hash) {
+                known.push(true);
+                continue;
+            }
+            let parent = match batch.get(&header.parent_hash) {
+                Some(&(index, td)) => Some((&headers[index], td)),
+                None => self
+                    .headers
+                    .get(&header.parent_hash)
+                    .map(|entry| (&entry.header, entry.total_difficulty)),
+            };
+            let checked = match parent {
+                Some((parent, td)) => {
+                    self.check_child(parent, td, header).map(|_| td)
+                },
+                None => Err(HeaderError::UnknownParent),
+            };
+            let td = match checked {
+                Ok(td) => td,
+                Err(error) => {
+                    failure = Some((i, error));
+                    break;
+                },
+            };
+            batch.insert(hash, (i, td.saturating_add(header.difficulty)));
+            known.push(false);
+        }
+
+        let new: Vec<BlockHeader> = headers
+            .iter()
+            .zip(&known)
+            .filter(|(_, known)| !**known)
+            .map(|(header, _)| header.clone())
+            .collect();
+        let mut seals = self.verifier.verify(&new).results.into_iter();
+        let mut imported = Vec::with_capacity(known.len());
+        for (i, (header, known)) in
+            headers.into_iter().zip(known).enumerate()
+        {
+            if known {
+                imported.push(Imported::Known);
+                continue;
+            }
+            let seal = seals.next().expect("a seal per new header");
+            seal.map_err(|e| (i, HeaderError::InvalidSeal(e)))?;
+            imported.push(self.insert(header));
+        }
+        match failure {
+            Some(failure) => Err(failure),
+            None => Ok(imported),
+        }
+    }
+
+    /// Check `header` against its parent, unless it is known already. Fails
+    /// closed on proof of stake headers.
+    fn check(&self, header: &BlockHeader) -> Result<bool, HeaderError> {
+        if self.headers.contains_key(&header.hash()) {
+            return Ok(true);
+        }
+        let parent = self
+            .headers
+            .get(&header.parent_hash)
+            .ok_or(HeaderError::UnknownParent)?;
+        self.check_child(&parent.header, parent.total_difficulty, header)?;
+        Ok(false)
+    }
+
+    /// Check `header` against its `parent`, of total difficulty `td`.
+    fn check_child(
+        &self,
+        parent: &BlockHeader,
+        td: U256,
+        header: &BlockHeader,
+    ) -> Result<(), HeaderError> {
+        validate_header_with_td(&self.config, parent, td, header)?;
+        if self.config.is_merged(td) {
+            return Err(HeaderError::PastMerge);
+        }
+        Ok(())
+    }
+
+    fn ecbp1100_active(&self, number: U256) -> bool {
+        match &self.config.ecbp1100 {
+            Some(blocks) => {
+                number >= blocks.start.into() && number < blocks.end.into()
+            },
+            None => false,
+        }
+    }
+
+    /// Insert a checked header and update the canonical chain.
+    fn insert(&mut self, header: BlockHeader) -> Imported {
+        let hash = header.hash();
+        let total_difficulty = self.headers[&header.parent_hash]
+            .total_difficulty
+            .saturating_add(header.difficulty);
+        let head_difficulty = self.headers[&self.head].total_difficulty;
+        self.headers.insert(
+            hash,
+            Entry {
+                header,
+                total_difficulty,
+            },
+        );
+        if total_difficulty <= head_difficulty {
+            return Imported::SideChain;
+        }
+
+        // walk the new branch back to the canonical chain.
+        let old_head = self.head;
+        let mut branch = Vec::new();
+        let mut ancestor = hash;
+        loop {
+            let header = &self.headers[&ancestor].header;
+            if self.canonical.get(&header.number) == Some(&ancestor) {
+                break;
+            }
+            branch.push((header.number, ancestor));
+            ancestor = header.parent_hash;
+        }
+        let ancestor_entry = &self.headers[&ancestor];
+        let number = ancestor_entry.header.number;
+        let old_number = self.headers[&old_head].header.number;
+        if ancestor != old_head && self.ecbp1100_active(old_number) {
+            let new_header = &self.headers[&hash].header;
+            let age = new_header
+                .timestamp
+                .saturating_sub(ancestor_entry.header.timestamp);
+            let base = ancestor_entry.total_difficulty;
+            let allowed = ecbp1100_allows(
+                age,
+                head_difficulty - base,
+                total_difficulty - base,
+            );
+            if !allowed {
+                return Imported::SideChain;
+            }
+        }
+
+        let new_number = self.headers[&hash].header.number;
+        self.canonical.extend(branch);
+        // drop the old canonical blocks past the new head.
+        let stale: Vec<U256> =
+            self.canonical.range(new_number + 1..).map(|(n, _)| *n).collect();
+        for number in stale {
+            self.canonical.remove(&number);
+        }
+        self.head = hash;
+        Imported::NewHead(HeadChange {
+            old_head,
+            new_head: hash,
+            common_ancestor: ancestor,
+            reorg_depth: (old_number - number).low_u64(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use ethereum_types::H64;
+
+    fn config() -> ChainConfig {
+        ChainConfig {
+            difficulty: DifficultyConfig {
+                minimum: U256::from(2),
+                bound_divisor: U256::from(2),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn genesis() -> BlockHeader {
+        BlockHeader {
+            uncles_hash: uncles_hash(&[]),
+            difficulty: U256::from(2),
+            gas_limit: 8_000_000,
+            timestamp: 1000,
+            ..Default::default()
+        }
+    }
+
+    /// A sealed child of `parent`, `elapsed` seconds later.
+    fn child(
+        parent: &BlockHeader,
+        elapsed: u64,
+        dag: &LightDAG,
+    ) -> BlockHeader {
+        let mut header = parent.clone();
+        header.parent_hash = parent.hash();
+        header.number = parent.number + 1;
+        header.timestamp = parent.timestamp + elapsed;
+        header.difficulty =
+            calc_difficulty(&config().difficulty, parent, header.timestamp);
+        header.seal_light(dag, 0..=u64::MAX).unwrap()
+    }
+
+    #[test]
+    fn header_rules() {
+        let config = ChainConfig::default();
+        let parent = genesis();
+        let mut header = parent.clone();
+        header.number = 1.into();
+        header.timestamp = 1005;
+        header.difficulty = calc_difficulty(&config.difficulty, &parent, 1005);
+        assert_eq!(validate_header(&config, &parent, &header), Ok(()));
+
+        let check = |f: fn(&mut BlockHeader)| {
+            let mut header = header.clone();
+            f(&mut header);
+            validate_header(&config, &parent, &header)
+        };
+        assert_eq!(
+            check(|h| h.number = 2.into()),
+            Err(HeaderError::InvalidNumber),
+        );
+        assert_eq!(
+            check(|h| h.timestamp = 1000),
+            Err(HeaderError::InvalidTimestamp),
+        );
+        assert_eq!(
+            check(|h| h.gas_limit += 8_000_000 / 1024),
+            Err(HeaderError::InvalidGasLimit),
+        );
+        assert_eq!(check(|h| h.gas_limit += 7000), Ok(()));
+        assert_eq!(
+            check(|h| h.gas_used = h.gas_limit + 1),
+            Err(HeaderError::InvalidGasUsed),
+        );
+        assert_eq!(
+            check(|h| h.extra_data = vec![0; 33]),
+            Err(HeaderError::ExtraDataTooLong(33)),
+        );
+        assert_eq!(
+            check(|h| h.difficulty += U256::one()),
+            Err(HeaderError::InvalidDifficulty(131_072.into())),
+        );
+    }
+
+    #[test]
+    fn london() {
+        let config = ChainConfig {
+            london_block: Some(1),
+            ..Default::default()
+        };
+        let parent = genesis();
+        let mut header = parent.clone();
+        header.number = 1.into();
+        header.timestamp = 1005;
+        header.difficulty = calc_difficulty(&config.difficulty, &parent, 1005);
+        // the gas limit may double at the fork.
+        header.gas_limit = 16_000_000;
+        header.base_fee_per_gas = Some(INITIAL_BASE_FEE.into());
+        assert_eq!(validate_header(&config, &parent, &header), Ok(()));
+        let mut missing = header.clone();
+        missing.base_fee_per_gas = None;
+        assert_eq!(
+            validate_header(&config, &parent, &missing),
+            Err(HeaderError::InvalidBaseFee(Some(INITIAL_BASE_FEE.into()))),
+        );
+        assert_eq!(
+            validate_header(&ChainConfig::default(), &parent, &header),
+            Err(HeaderError::InvalidGasLimit),
+        );
+
+        // full blocks raise the base fee by 1/8, empty ones lower it as much.
+        let base_fee = |gas_used| {
+            let mut parent = header.clone();
+            parent.gas_used = gas_used;
+            calc_base_fee(&config, &parent, 2.into()).unwrap()
+        };
+        assert_eq!(base_fee(8_000_000), INITIAL_BASE_FEE.into());
+        assert_eq!(base_fee(16_000_000), 1_125_000_000.into());
+        assert_eq!(base_fee(0), 875_000_000.into());
+        assert_eq!(base_fee(8_000_001), 1_000_000_015.into());
+        assert_eq!(calc_base_fee(&config, &parent, 0.into()), None);
+    }
+
+    #[test]
+    fn epochs() {
+        let config = ChainConfig {
+            ecip1099_block: Some(60_000),
+            ..Default::default()
+        };
+        assert_eq!(config.epoch(59_999), 1);
+        assert_eq!(config.epoch(60_000), 1);
+        assert_eq!(config.epoch(120_000), 2);
+        assert_eq!(config.epoch_length(0), EPOCH_LENGTH);
+        assert_eq!(config.seed_epoch(59_999), 1);
+        assert_eq!(config.seed_epoch(60_000), 2);
+        assert_eq!(config.seed_epoch(120_000), 4);
+    }
+
+    #[test]
+    fn merge() {
+        let config = ChainConfig {
+            terminal_total_difficulty: Some(1000.into()),
+            ..config()
+        };
+        let parent = genesis();
+        let mut pow = parent.clone();
+        pow.number = 1.into();
+        pow.timestamp = 1012;
+        pow.difficulty = calc_difficulty(&config.difficulty, &parent, 1012);
+        let mut pos = pow.clone();
+        pos.difficulty = U256::zero();
+        let check = |td: u64, header: &BlockHeader| {
+            validate_header_with_td(&config, &parent, td.into(), header)
+        };
+        assert_eq!(check(999, &pow), Ok(()));
+        assert_eq!(
+            check(999, &pos),
+            Err(HeaderError::InvalidDifficulty(pow.difficulty)),
+        );
+        assert_eq!(check(1000, &pos), Ok(()));
+        assert_eq!(check(1000, &pow), Err(HeaderError::PowAfterMerge));
+        let mut nonce = pos.clone();
+        nonce.nonce = H64::from_low_u64_be(1);
+        assert_eq!(check(1000, &nonce), Err(HeaderError::NonZeroNonce));
+        let mut uncles = pos.clone();
+        uncles.uncles_hash = H256::zero();
+        assert_eq!(check(1000, &uncles), Err(HeaderError::NonEmptyUncles));
+        let mut timestamp = pos.clone();
+        timestamp.timestamp = 1000;
+        assert_eq!(check(1000, &timestamp), Err(HeaderError::InvalidTimestamp));
+
+        // the chain takes the terminal block, then fails closed.
+        let dag = LightDAG::tiny(0);
+        let config = ChainConfig {
+            terminal_total_difficulty: Some(4.into()),
+            ..config
+        };
+        let mut chain = HeaderChain::new(config, parent.clone(), 2.into());
+        chain.insert_dag(LightDAG::tiny(0));
+        let terminal = child(&parent, 10, &dag);
+        let imported = chain.import(terminal.clone());
+        assert!(matches!(imported, Ok(Imported::NewHead(_))));
+        let pow = child(&terminal, 10, &dag);
+        assert_eq!(chain.import(pow.clone()), Err(HeaderError::PowAfterMerge));
+        let mut pos = pow;
+        pos.difficulty = U256::zero();
+        pos.nonce = H64::zero();
+        assert_eq!(chain.import(pos), Err(HeaderError::PastMerge));
+    }
+
+    #[test]
+    fn reorgs() {
+        let dag = LightDAG::tiny(0);
+        let genesis = genesis();
+        let mut chain = HeaderChain::new(config(), genesis.clone(), 2.into());
+        chain.insert_dag(LightDAG::tiny(0));
+
+        // a: genesis <- a1 <- a2, every 10 seconds, at difficulty 2.
+        let a1 = child(&genesis, 10, &dag);
+        let a2 = child(&a1, 10, &dag);
+        let imported = chain.import_all(vec![a1.clone(), a2.clone()]).unwrap();
+        assert_eq!(
+            imported[1],
+            Imported::NewHead(HeadChange {
+                old_head: a1.hash(),
+                new_head: a2.hash(),
+                common_ancestor: a1.hash(),
+                reorg_depth: 0,
+            }),
+        );
+        assert_eq!(chain.import(a2.clone()), Ok(Imported::Known));
+
+        // b: genesis <- b1 <- b2, b1 is a second after genesis and weighs 3.
+        let b1 = child(&genesis, 1, &dag);
+        assert_eq!(b1.difficulty, U256::from(3));
+        assert_eq!(chain.import(b1.clone()), Ok(Imported::SideChain));
+        assert_eq!(chain.total_difficulty(b1.hash()), Some(5.into()));
+        let b2 = child(&b1, 10, &dag);
+        assert_eq!(
+            chain.import(b2.clone()),
+            Ok(Imported::NewHead(HeadChange {
+                old_head: a2.hash(),
+                new_head: b2.hash(),
+                common_ancestor: genesis.hash(),
+                reorg_depth: 2,
+            })),
+        );
+        assert_eq!(chain.head(), &b2);
+        assert_eq!(chain.canonical_hash(1.into()), Some(b1.hash()));
+        assert_eq!(chain.canonical_hash(2.into()), Some(b2.hash()));
+
+        let mut forged = child(&b2, 10, &dag);
+        forged.mix_hash = Default::default();
+        let result = chain.import_all(vec![child(&a2, 10, &dag), forged]);
+        assert!(matches!(result, Err((1, HeaderError::InvalidSeal(_)))));
+        let orphan = child(&b2, 10, &dag);
+        let result = chain.import(child(&orphan, 10, &dag));
+        assert_eq!(result, Err(HeaderError::UnknownParent));
+
+        // headers are checked against the earlier ones of the batch, and
+        // those failing have no cache built, here the one of epoch 1.
+        let c3 = child(&b2, 10, &dag);
+        let c4 = child(&c3, 10, &dag);
+        let mut far = child(&c4, 10, &dag);
+        far.number = EPOCH_LENGTH.into();
+        let result = chain.import_all(vec![c3, c4.clone(), far]);
+        assert_eq!(result, Err((2, HeaderError::InvalidNumber)));
+        assert_eq!(chain.head(), &c4);
+    }
+
+    #[test]
+    fn ecbp1100() {
+        let dag = LightDAG::tiny(0);
+        let genesis = genesis();
+        let config = ChainConfig {
+            ecbp1100: Some(0..u64::MAX),
+            ..config()
+        };
+        let mut chain = HeaderChain::new(config, genesis.clone(), 2.into());
+        chain.insert_dag(LightDAG::tiny(0));
+        let a1 = child(&genesis, 10, &dag);
+        chain.import(a1.clone()).unwrap();
+
+        // a recent fork only has to be heavier.
+        let b1 = child(&genesis, 1, &dag);
+        let imported = chain.import(b1.clone()).unwrap();
+        assert!(matches!(imported, Imported::NewHead(_)));
+
+        // an old one is too light.
+        let c1 = child(&genesis, 20_000, &dag);
+        let c2 = child(&c1, 10, &dag);
+        let imported = chain.import_all(vec![c1, c2.clone()]).unwrap();
+        assert_eq!(imported, [Imported::SideChain, Imported::SideChain]);
+        assert_eq!(chain.total_difficulty(c2.hash()), Some(6.into()));
+        assert_eq!(chain.head_hash(), b1.hash());
+    }
+}
@@ -0,0 +1,339 @@
+//! A block producer for devnets.
+//!
+//! [`Devnet`] grows a chain of valid Ethash headers on top of a genesis, for
+//! integration tests: every block is a child of the previous one, a chosen
+//! number of seconds later, with the difficulty and base fee the
+//! [`ChainConfig`] of the devnet computes, and optionally includes uncles.
+//! Blocks are sealed with the [`LightDAG`] the devnet is given, usually a
+//! tiny one, while a low difficulty keeps mining cheap.
+//!
+//! The chain can be dumped as RLP fixtures, in the format of
+//! `tests/fixtures`, and, with the `json` feature, as JSON-RPC blocks.
+
+use alloc::{string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
+
+use ethereum_types::U256;
+
+use super::chain::{calc_base_fee, ChainConfig, ELASTICITY_MULTIPLIER};
+use super::difficulty::calc_difficulty;
+use super::types::BlockHeader;
+use super::uncles::{uncles_hash, Ancestor, MAX_UNCLE_DEPTH};
+use crate::LightDAG;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DevnetError {
+    // Uncle depth is not from 1 to `MAX_UNCLE_DEPTH`, or past the genesis
+    InvalidUncleDepth(usize),
+    // Timestamp of the block does not fit in 64 bits
+    TimestampOverflow,
+}
+
+pub struct Devnet {
+    config: ChainConfig,
+    dag: LightDAG,
+    /// Blocks of the chain, genesis first.
+    blocks: Vec<Ancestor>,
+    /// Headers produced so far, to tell the siblings of a block apart.
+    produced: u64,
+}
+
+impl Devnet {
+    /// A devnet on top of `genesis`, which is not sealed, sealing blocks
+    /// with `dag` as long as it is valid for them, and with the DAG of their
+    /// epoch past it.
+    pub fn new(
+        config: ChainConfig,
+        genesis: BlockHeader,
+        dag: LightDAG,
+    ) -> Self {
+        Self {
+            config,
+            dag,
+            blocks: alloc::vec![Ancestor {
+                header: genesis,
+                uncles: Vec::new(),
+            }],
+            produced: 0,
+        }
+    }
+
+    /// A genesis of the given difficulty and gas limit, at `timestamp`.
+    pub fn genesis(
+        difficulty: U256,
+        gas_limit: u64,
+        timestamp: u64,
+    ) -> BlockHeader {
+        BlockHeader {
+            uncles_hash: uncles_hash(&[]),
+            difficulty,
+            gas_limit,
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &ChainConfig { &self.config }
+
+    /// Blocks of the chain, genesis first, with their uncles.
+    pub fn blocks(&self) -> &[Ancestor] { &self.blocks }
+
+    pub fn head(&self) -> &BlockHeader {
+        &self.blocks[self.blocks.len() - 1].header
+    }
+
+    /// The ancestors of the next block, parent first, as
+    /// [`super::uncles::check_uncles`] takes them.
+    pub fn ancestors(&self) -> Vec<Ancestor> {
+        self.blocks.iter().rev().take(MAX_UNCLE_DEPTH).cloned().collect()
+    }
+
+    /// Produce the next block, `elapsed` seconds after the head.
+    pub fn produce(
+        &mut self,
+        elapsed: u64,
+    ) -> Result<&BlockHeader, DevnetError> {
+        self.produce_with_uncles(elapsed, Vec::new())
+    }
+
+    /// Produce the next block, `elapsed` seconds after the head, including
+    /// `uncles`, see [`Devnet::uncle`].
+    pub fn produce_with_uncles(
+        &mut self,
+        elapsed: u64,
+        uncles: Vec<BlockHeader>,
+    ) -> Result<&BlockHeader, DevnetError> {
+        let parent = self.head().clone();
+        let header = self.child(&parent, elapsed, uncles_hash(&uncles))?;
+        self.blocks.push(Ancestor { header, uncles });
+        Ok(self.head())
+    }
+
+    /// A sealed sibling of the block `depth` generations behind the next
+    /// one, `elapsed` seconds after its parent. It is a valid uncle of the
+    /// next block for a `depth` from 2 to [`MAX_UNCLE_DEPTH`]. A `depth` of
+    /// 1 gives a sibling of the next block.
+    pub fn uncle(
+        &mut self,
+        depth: usize,
+        elapsed: u64,
+    ) -> Result<BlockHeader, DevnetError> {
+        if !(1..=MAX_UNCLE_DEPTH.min(self.blocks.len())).contains(&depth) {
+            return Err(DevnetError::InvalidUncleDepth(depth));
+        }
+        let parent = self.blocks[self.blocks.len() - depth].header.clone();
+        self.child(&parent, elapsed, uncles_hash(&[]))
+    }
+
+    fn child(
+        &mut self,
+        parent: &BlockHeader,
+        elapsed: u64,
+        uncles_hash: ethereum_types::H256,
+    ) -> Result<BlockHeader, DevnetError> {
+        let timestamp = parent
+            .timestamp
+            .checked_add(elapsed)
+            .ok_or(DevnetError::TimestampOverflow)?;
+        self.produced += 1;
+        let number = parent.number + 1;
+        let gas_limit = if self.config.is_london(number)
+            && !self.config.is_london(parent.number)
+        {
+            parent.gas_limit * ELASTICITY_MULTIPLIER
+        } else {
+            parent.gas_limit
+        };
+        let header = BlockHeader {
+            parent_hash: parent.hash(),
+            uncles_hash,
+            author: parent.author,
+            state_root: parent.state_root,
+            transactions_root: parent.transactions_root,
+            receipts_root: parent.receipts_root,
+            difficulty: calc_difficulty(
+                &self.config.difficulty,
+                parent,
+                timestamp,
+            ),
+            number,
+            gas_limit,
+            timestamp,
+            // siblings would be the same block otherwise.
+            extra_data: self.produced.to_be_bytes().to_vec(),
+            base_fee_per_gas: calc_base_fee(&self.config, parent, number),
+            ..Default::default()
+        };
+        if !self.dag.is_valid_for(number) {
+            self.dag = self.config.light_dag(number.low_u64());
+        }
+        Ok(header
+            .seal_light(&self.dag, 0..=u64::MAX)
+            .expect("a nonce is found before the nonces run out"))
+    }
+
+    /// The RLP of the headers of the chain, genesis first, in hex.
+    pub fn rlp_fixtures(&self) -> Vec<String> {
+        self.blocks
+            .iter()
+            .map(|block| hex::encode(rlp::encode(&block.header)))
+            .collect()
+    }
+
+    /// The chain as an array of JSON-RPC block objects, genesis first, with
+    /// the hashes of their `uncles`.
+    #[cfg(feature = "json")]
+    pub fn to_rpc(&self) -> serde_json::Value {
+        use serde_json::Value;
+
+        let blocks = self.blocks.iter().map(|block| {
+            let mut rpc = block.header.to_rpc();
+            let uncles = block.uncles.iter().map(|uncle| {
+                Value::from(alloc::format!("0x{}", hex::encode(uncle.hash())))
+            });
+            rpc["uncles"] = Value::Array(uncles.collect());
+            rpc
+        });
+        Value::Array(blocks.collect())
+    }
+
+    /// Write the chain into `dir` as fixtures: `<number>.rlp` holds the RLP
+    /// of a header, in hex, `<number>.uncles.rlp` the one of its uncles if
+    /// it has any and, with the `json` feature, `chain.json` the chain as
+    /// JSON-RPC blocks.
+    #[cfg(feature = "std")]
+    pub fn write_fixtures<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        for block in &self.blocks {
+            let number = block.header.number;
+            let rlp = hex::encode(rlp::encode(&block.header));
+            fs::write(dir.join(format!("{}.rlp", number)), rlp + "\n")?;
+            if !block.uncles.is_empty() {
+                let uncles = rlp::encode_list::<BlockHeader, _>(&block.uncles);
+                let rlp = hex::encode(uncles);
+                let path = dir.join(format!("{}.uncles.rlp", number));
+                fs::write(path, rlp + "\n")?;
+            }
+        }
+        #[cfg(feature = "json")]
+        {
+            let json = format!("{:#}\n", self.to_rpc());
+            fs::write(dir.join("chain.json"), json)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::difficulty::DifficultyConfig;
+    use crate::proof::uncles::check_uncles;
+
+    fn devnet() -> Devnet {
+        let config = ChainConfig {
+            difficulty: DifficultyConfig {
+                minimum: U256::from(2),
+                bound_divisor: U256::from(2),
+                byzantium_block: Some(0),
+                ..Default::default()
+            },
+            london_block: Some(2),
+            ..Default::default()
+        };
+        let genesis = Devnet::genesis(U256::from(4), 8_000_000, 1000);
+        Devnet::new(config, genesis, LightDAG::tiny(0))
+    }
+
+    #[test]
+    fn produce() {
+        let mut devnet = devnet();
+        devnet.produce(10).unwrap();
+        devnet.produce(5).unwrap();
+        let uncle = devnet.uncle(2, 7).unwrap();
+        let ancestors = devnet.ancestors();
+        let uncles = vec![uncle];
+        let block = devnet.produce_with_uncles(20, uncles.clone()).unwrap();
+        let block = block.clone();
+        assert_eq!(check_uncles(&block, &uncles, &ancestors), Ok(()));
+        assert_eq!(block.number, U256::from(3));
+        assert_eq!(block.timestamp, 1035);
+        assert_eq!(block.gas_limit, 16_000_000);
+        assert!(block.base_fee_per_gas.is_some());
+
+        #[cfg(feature = "std")]
+        {
+            use crate::proof::chain::HeaderChain;
+
+            let blocks = devnet.blocks();
+            let genesis = blocks[0].header.clone();
+            let config = devnet.config().clone();
+            let mut chain = HeaderChain::new(config, genesis, 4.into());
+            chain.insert_dag(LightDAG::tiny(0));
+            let headers = blocks[1..].iter().map(|b| b.header.clone());
+            assert!(chain.import_all(headers.collect()).is_ok());
+            assert_eq!(chain.head(), devnet.head());
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut devnet = devnet();
+        devnet.produce(10).unwrap();
+        let invalid = DevnetError::InvalidUncleDepth;
+        assert_eq!(devnet.uncle(0, 1), Err(invalid(0)));
+        // the chain holds the genesis and one block.
+        assert_eq!(devnet.uncle(3, 1), Err(invalid(3)));
+        assert!(devnet.uncle(2, 1).is_ok());
+        for _ in 0..MAX_UNCLE_DEPTH {
+            devnet.produce(10).unwrap();
+        }
+        let depth = MAX_UNCLE_DEPTH + 1;
+        assert_eq!(devnet.uncle(depth, 1), Err(invalid(depth)));
+        assert!(devnet.uncle(MAX_UNCLE_DEPTH, 1).is_ok());
+
+        let overflow = Err(DevnetError::TimestampOverflow);
+        assert_eq!(devnet.uncle(1, u64::MAX).map(|_| ()), overflow);
+        assert_eq!(devnet.produce(u64::MAX).map(|_| ()), overflow);
+        assert_eq!(devnet.head().number, U256::from(8));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn fixtures() {
+        let mut devnet = devnet();
+        devnet.produce(10).unwrap();
+        devnet.produce(10).unwrap();
+        let uncle = devnet.uncle(2, 3).unwrap();
+        devnet.produce_with_uncles(10, vec![uncle.clone()]).unwrap();
+        let dir = std::env::temp_dir()
+            .join(format!("ethash-devnet-{}", std::process::id()));
+        devnet.write_fixtures(&dir).unwrap();
+
+        let read = |name: &str| {
+            let hex = fs::read_to_string(dir.join(name)).unwrap();
+            hex::decode(hex.trim()).unwrap()
+        };
+        let rlp = read("3.rlp");
+        let header: BlockHeader = rlp::decode(&rlp).unwrap();
+        assert_eq!(&header, devnet.head());
+        assert_eq!(hex::encode(&rlp), devnet.rlp_fixtures()[3]);
+        let uncles: Vec<BlockHeader> = rlp::decode_list(&read("3.uncles.rlp"));
+        assert_eq!(uncles, [uncle]);
+        assert!(!dir.join("2.uncles.rlp").exists());
+
+        #[cfg(feature = "json")]
+        {
+            let json = fs::read_to_string(dir.join("chain.json")).unwrap();
+            let chain: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(chain, devnet.to_rpc());
+            let head = BlockHeader::from_rpc(&chain[3]).unwrap();
+            assert_eq!(&head, devnet.head());
+            let uncle = format!("0x{}", hex::encode(uncles[0].hash()));
+            assert_eq!(chain[3]["uncles"][0], uncle);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
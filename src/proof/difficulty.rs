@@ -0,0 +1,207 @@
+//! Difficulty adjustment.
+//!
+//! The difficulty of a block follows the one of its parent, adjusted by the
+//! time elapsed between them, plus the exponential "difficulty bomb". The
+//! adjustment changed over the forks of the chain:
+//!
+//! - Frontier: up by `1/2048` below 13 seconds, down by as much otherwise.
+//! - Homestead (EIP-2): by `max(1 - elapsed / 10, -99) / 2048`.
+//! - Byzantium (EIP-100): by `max(u - elapsed / 9, -99) / 2048`, where `u` is
+//!   2 if the parent includes uncles and 1 otherwise.
+//!
+//! The bomb adds `2^(n / 100000 - 2)`, where `n` is the block number minus
+//! the delays of the bomb, and can be paused or removed altogether.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use ethereum_types::U256;
+
+use super::types::BlockHeader;
+use super::uncles::uncles_hash;
+
+/// Blocks per period of the difficulty bomb.
+const BOMB_PERIOD: u64 = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DifficultyConfig {
+    /// Lowest difficulty the adjustment goes down to.
+    pub minimum: U256,
+    /// Fraction of the parent difficulty a unit of adjustment is.
+    pub bound_divisor: U256,
+    /// Frontier target of the time between blocks, in seconds.
+    pub duration_limit: u64,
+    pub homestead_block: u64,
+    /// Start of the uncle-aware adjustment of EIP-100.
+    pub byzantium_block: Option<u64>,
+    /// `(block, delay)`: from `block` on, the bomb counts `delay` blocks
+    /// less. Sorted by block.
+    pub bomb_delays: Vec<(u64, u64)>,
+    /// Blocks over which the bomb stands still, and is delayed by their
+    /// count afterwards (ECIP-1010).
+    pub bomb_pause: Option<Range<u64>>,
+    /// From this block on there is no bomb at all (ECIP-1041).
+    pub bomb_defuse_block: Option<u64>,
+}
+
+impl Default for DifficultyConfig {
+    fn default() -> Self {
+        Self {
+            minimum: U256::from(131_072),
+            bound_divisor: U256::from(2048),
+            duration_limit: 13,
+            homestead_block: 0,
+            byzantium_block: None,
+            bomb_delays: Vec::new(),
+            bomb_pause: None,
+            bomb_defuse_block: None,
+        }
+    }
+}
+
+impl DifficultyConfig {
+    /// The block number the bomb of block `number` is computed with.
+    fn bomb_number(&self, number: u64) -> u64 {
+        let delay = self
+            .bomb_delays
+            .iter()
+            .take_while(|(block, _)| number >= *block)
+            .last()
+            .map_or(0, |(_, delay)| *delay);
+        let number = match &self.bomb_pause {
+            Some(pause) if number >= pause.end => {
+                number - (pause.end - pause.start)
+            },
+            Some(pause) if number >= pause.start => pause.start,
+            _ => number,
+        };
+        number.saturating_sub(delay)
+    }
+
+    /// The bomb of block `number`.
+    pub fn bomb(&self, number: u64) -> U256 {
+        if self.bomb_defuse_block.is_some_and(|block| number >= block) {
+            return U256::zero();
+        }
+        let period = self.bomb_number(number) / BOMB_PERIOD;
+        if period > 1 {
+            U256::one() << (period - 2)
+        } else {
+            U256::zero()
+        }
+    }
+}
+
+/// The difficulty of a child of `parent` with the given `timestamp`.
+pub fn calc_difficulty(
+    config: &DifficultyConfig,
+    parent: &BlockHeader,
+    timestamp: u64,
+) -> U256 {
+    let number = parent.number.low_u64() + 1;
+    let elapsed = timestamp.saturating_sub(parent.timestamp);
+    let step = parent.difficulty / config.bound_divisor;
+    // the adjustment, in steps, with its sign.
+    let byzantium = config.byzantium_block.is_some_and(|b| number >= b);
+    let (up, steps) = if byzantium {
+        let uncles = parent.uncles_hash != uncles_hash(&[]);
+        signed_steps(if uncles { 2 } else { 1 }, elapsed / 9)
+    } else if number >= config.homestead_block {
+        signed_steps(1, elapsed / 10)
+    } else {
+        (elapsed < config.duration_limit, 1)
+    };
+    let difficulty = if up {
+        parent.difficulty.saturating_add(step * steps)
+    } else {
+        parent.difficulty.saturating_sub(step * steps)
+    };
+    difficulty.max(config.minimum) + config.bomb(number)
+}
+
+/// `max(base - elapsed, -99)`, as a sign (up or not) and a magnitude.
+fn signed_steps(base: u64, elapsed: u64) -> (bool, u64) {
+    if elapsed <= base {
+        (true, base - elapsed)
+    } else {
+        (false, (elapsed - base).min(99))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent(number: u64, difficulty: u64, timestamp: u64) -> BlockHeader {
+        BlockHeader {
+            uncles_hash: uncles_hash(&[]),
+            difficulty: difficulty.into(),
+            number: number.into(),
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn adjustment() {
+        let frontier = DifficultyConfig {
+            homestead_block: u64::MAX,
+            ..Default::default()
+        };
+        let parent = parent(1000, 2048 * 1000, 100);
+        let calc = |config, timestamp| {
+            calc_difficulty(config, &parent, timestamp)
+        };
+        assert_eq!(calc(&frontier, 112), U256::from(2048 * 1000 + 1000));
+        assert_eq!(calc(&frontier, 113), U256::from(2048 * 1000 - 1000));
+
+        let homestead = DifficultyConfig::default();
+        assert_eq!(calc(&homestead, 109), U256::from(2048 * 1000 + 1000));
+        assert_eq!(calc(&homestead, 119), U256::from(2048 * 1000));
+        assert_eq!(calc(&homestead, 135), U256::from(2048 * 1000 - 2000));
+        // the adjustment is capped at 99 steps down.
+        assert_eq!(calc(&homestead, 5000), U256::from(2048 * 1000 - 99_000));
+
+        let byzantium = DifficultyConfig {
+            byzantium_block: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(calc(&byzantium, 117), U256::from(2048 * 1000));
+        let mut with_uncles = parent.clone();
+        with_uncles.uncles_hash = Default::default();
+        assert_eq!(
+            calc_difficulty(&byzantium, &with_uncles, 117),
+            U256::from(2048 * 1000 + 1000),
+        );
+
+        let low = self::parent(1000, 1000, 100);
+        let minimum = U256::from(131_072);
+        assert_eq!(calc_difficulty(&homestead, &low, 5000), minimum);
+    }
+
+    #[test]
+    fn bomb() {
+        let config = DifficultyConfig {
+            bomb_delays: vec![(4_370_000, 3_000_000), (7_280_000, 5_000_000)],
+            ..Default::default()
+        };
+        assert_eq!(config.bomb(199_999), U256::zero());
+        assert_eq!(config.bomb(200_000), U256::one());
+        assert_eq!(config.bomb(4_369_999), U256::one() << 41);
+        assert_eq!(config.bomb(4_370_000), U256::one() << 11);
+        assert_eq!(config.bomb(7_280_000), U256::one() << 20);
+
+        // ECIP-1010 and ECIP-1041.
+        let config = DifficultyConfig {
+            bomb_pause: Some(3_000_000..5_000_000),
+            bomb_defuse_block: Some(5_900_000),
+            ..Default::default()
+        };
+        assert_eq!(config.bomb(4_000_000), U256::one() << 28);
+        assert_eq!(config.bomb(5_000_000), U256::one() << 28);
+        assert_eq!(config.bomb(5_200_000), U256::one() << 30);
+        assert_eq!(config.bomb(5_900_000), U256::zero());
+    }
+}
@@ -0,0 +1,139 @@
+//! Byte encodings of dataset elements.
+//!
+//! A verifier contract reads the 128 bytes of a dataset element in some
+//! layout of its own, and hashes them into a Merkle leaf in that same
+//! layout. An [`ElementEncoding`] describes such a layout, so that the leaf
+//! hashes of the tree and the elements exported alongside proofs match what
+//! the verifier expects.
+
+use ethereum_types::H256;
+
+use super::mtree::Word;
+use super::WORD_LENGTH;
+
+/// Layout of a dataset element as seen by a verifier.
+pub trait ElementEncoding: Send + Sync {
+    /// Encode the element as it is stored in the dataset.
+    fn encode(word: &Word) -> [u8; WORD_LENGTH];
+
+    /// Inverse of [`ElementEncoding::encode`].
+    fn decode(bytes: &[u8; WORD_LENGTH]) -> Word;
+
+    /// The encoded element as four 256-bit words, as handed to verifiers.
+    fn to_words(word: &Word) -> [H256; 4] {
+        let bytes = Self::encode(word);
+        let mut words = [H256::zero(); 4];
+        for (w, chunk) in words.iter_mut().zip(bytes.chunks_exact(32)) {
+            w.0.copy_from_slice(chunk);
+        }
+        words
+    }
+
+    /// Inverse of [`ElementEncoding::to_words`].
+    fn from_words(words: &[H256; 4]) -> Word {
+        let mut bytes = [0u8; WORD_LENGTH];
+        for (chunk, w) in bytes.chunks_exact_mut(32).zip(words) {
+            chunk.copy_from_slice(w.as_bytes());
+        }
+        Self::decode(&bytes)
+    }
+}
+
+/// The [conventional](Word::conventional) encoding: every 32-byte quarter of
+/// the element is read as a little-endian integer and written out as a
+/// big-endian `uint256`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Conventional;
+
+impl ElementEncoding for Conventional {
+    fn encode(word: &Word) -> [u8; WORD_LENGTH] {
+        let (first, second) = word.conventional();
+        let mut bytes = [0u8; WORD_LENGTH];
+        bytes[..64].copy_from_slice(&first);
+        bytes[64..].copy_from_slice(&second);
+        bytes
+    }
+
+    fn decode(bytes: &[u8; WORD_LENGTH]) -> Word {
+        reverse_chunks(bytes, 32)
+    }
+}
+
+/// The element bytes exactly as stored in the dataset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct RawLittleEndian;
+
+impl ElementEncoding for RawLittleEndian {
+    fn encode(word: &Word) -> [u8; WORD_LENGTH] { word.0 }
+
+    fn decode(bytes: &[u8; WORD_LENGTH]) -> Word { Word(*bytes) }
+}
+
+/// The element as 32 packed `uint32` limbs: every little-endian 4-byte limb
+/// is written out big-endian, in order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct PackedU32;
+
+impl ElementEncoding for PackedU32 {
+    fn encode(word: &Word) -> [u8; WORD_LENGTH] { reverse_chunks(word, 4).0 }
+
+    fn decode(bytes: &[u8; WORD_LENGTH]) -> Word { reverse_chunks(bytes, 4) }
+}
+
+/// The element as a single little-endian 1024-bit integer, written out as
+/// four big-endian `uint256` words, most significant first. Unlike
+/// [`Conventional`], which keeps the quarters of the element in order, the
+/// last quarter comes first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct BigEndianWords;
+
+impl ElementEncoding for BigEndianWords {
+    fn encode(word: &Word) -> [u8; WORD_LENGTH] {
+        reverse_chunks(&word.0, WORD_LENGTH).0
+    }
+
+    fn decode(bytes: &[u8; WORD_LENGTH]) -> Word {
+        reverse_chunks(bytes, WORD_LENGTH)
+    }
+}
+
+fn reverse_chunks(bytes: &[u8; WORD_LENGTH], size: usize) -> Word {
+    let mut word = Word(*bytes);
+    word.0.chunks_exact_mut(size).for_each(|c| c.reverse());
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip<E: ElementEncoding>(word: &Word) -> [u8; WORD_LENGTH] {
+        let bytes = E::encode(word);
+        assert_eq!(&E::decode(&bytes), word);
+        assert_eq!(&E::from_words(&E::to_words(word)), word);
+        bytes
+    }
+
+    #[test]
+    fn encodings() {
+        let mut raw = [0u8; WORD_LENGTH];
+        raw.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        let word = Word(raw);
+
+        assert_eq!(roundtrip::<RawLittleEndian>(&word), raw);
+        let packed = roundtrip::<PackedU32>(&word);
+        assert_eq!(&packed[..8], &[3, 2, 1, 0, 7, 6, 5, 4]);
+        let conventional = roundtrip::<Conventional>(&word);
+        assert_eq!(conventional[0], 31);
+        let words = Conventional::to_words(&word);
+        assert_eq!(words, word.clone().into_h256_array());
+
+        let big_endian = roundtrip::<BigEndianWords>(&word);
+        assert_eq!(big_endian[0], 127);
+        assert_eq!(big_endian[WORD_LENGTH - 1], 0);
+        let words = BigEndianWords::to_words(&word);
+        let conventional = Conventional::to_words(&word);
+        assert_eq!(words[0], conventional[3]);
+        assert_eq!(words[3], conventional[0]);
+    }
+}
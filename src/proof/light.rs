@@ -0,0 +1,421 @@
+//! Dataset Merkle trees computed from the light cache.
+//!
+//! Every dataset element is two dataset items, which [`calc_dataset_item`]
+//! derives from the cache alone. Instead of materialising the whole dataset,
+//! the leaves are derived on the fly and streamed into a [`MerkleStream`], so
+//! that computing the root of an epoch only takes the cache and `O(depth)`
+//! extra memory.
+//!
+//! Proofs are generated the same way: [`CachedLevels`] keeps the upper levels
+//! of the tree, down to [`CACHE_LEVEL`](super::CACHE_LEVEL) levels below the
+//! root, and the subtree under the cached node of an element is recomputed
+//! from the cache whenever the element is proven.
+//!
+//! [`calc_dataset_item`]: crate::calc_dataset_item
+
+use alloc::{vec, vec::Vec};
+use core::marker::PhantomData;
+
+use ethereum_types::H128;
+
+use super::mtree::{
+    self, Hash, MerkleHasher, MerkleStream, MerkleTreeError, Sha256Hasher, Word,
+};
+use super::types::BlockWithProofs;
+use super::{CACHE_LEVEL, HASH_LENGTH, WORD_LENGTH};
+
+/// Height of the subtrees hashed as a single task, i.e. `2^10` leaves.
+const SUBTREE_HEIGHT: usize = 10;
+
+/// The dataset element at `index`, derived from the light `cache`.
+pub fn dataset_word(cache: &[u8], index: usize) -> Word {
+    let mut word = [0u8; WORD_LENGTH];
+    let (first, second) = word.split_at_mut(WORD_LENGTH / 2);
+    first
+        .copy_from_slice(crate::calc_dataset_item(cache, 2 * index).as_bytes());
+    second.copy_from_slice(
+        crate::calc_dataset_item(cache, 2 * index + 1).as_bytes(),
+    );
+    Word(word)
+}
+
+/// Same as [`calc_dataset_merkle_root`](super::calc_dataset_merkle_root),
+/// deriving the dataset from the light cache of the epoch.
+pub fn calc_dataset_merkle_root_from_cache(epoch: usize, cache: &[u8]) -> H128 {
+    calc_dataset_merkle_root_from_cache_with_hasher::<Sha256Hasher>(
+        epoch, cache,
+    )
+}
+
+/// Same as [`calc_dataset_merkle_root_from_cache`], for a tree hashed with
+/// `H`.
+pub fn calc_dataset_merkle_root_from_cache_with_hasher<H: MerkleHasher>(
+    epoch: usize,
+    cache: &[u8],
+) -> H128 {
+    let (leaves, depth) = dataset_shape(epoch);
+    let root = stream_root::<H>(cache, leaves, depth, SUBTREE_HEIGHT);
+    H128::from_slice(&root.0)
+}
+
+/// Root of the zero-padded subtree of the given height holding the leaves
+/// `start..end`.
+fn subtree_root<H: MerkleHasher>(
+    cache: &[u8],
+    start: usize,
+    end: usize,
+    height: usize,
+) -> Hash {
+    let mut stream = MerkleStream::<H>::new(height);
+    for index in start..end {
+        let leaf = H::hash_element(&dataset_word(cache, index));
+        stream
+            .push_leaf(leaf)
+            .expect("subtree has room for its leaves");
+    }
+    stream.root()
+}
+
+fn stream_root<H: MerkleHasher>(
+    cache: &[u8],
+    leaves: usize,
+    depth: usize,
+    subtree_height: usize,
+) -> Hash {
+    let height = subtree_height.min(depth);
+    let mut stream = MerkleStream::<H>::new(depth);
+    for_each_subtree_root::<H, _>(cache, leaves, height, |root| {
+        stream
+            .push_node(height, root)
+            .expect("dataset fits in a tree of its depth")
+    });
+    stream.root()
+}
+
+/// Call `f` with the roots of the subtrees of the given height over the
+/// `leaves` first leaves, from left to right.
+#[cfg(not(feature = "std"))]
+fn for_each_subtree_root<H: MerkleHasher, F: FnMut(Hash)>(
+    cache: &[u8],
+    leaves: usize,
+    height: usize,
+    mut f: F,
+) {
+    for start in (0..leaves).step_by(1 << height) {
+        let end = leaves.min(start + (1 << height));
+        f(subtree_root::<H>(cache, start, end, height));
+    }
+}
+
+#[cfg(feature = "std")]
+fn for_each_subtree_root<H: MerkleHasher, F: FnMut(Hash)>(
+    cache: &[u8],
+    leaves: usize,
+    height: usize,
+    mut f: F,
+) {
+    use rayon::prelude::*;
+
+    // setup rayon thread pool.
+    let _ = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_cpus::get())
+        .build_global()
+        .is_ok();
+
+    let subtrees = leaves.div_ceil(1 << height);
+    // a few subtrees per thread at once, to bound the memory in use.
+    let batch = 4 * rayon::current_num_threads();
+    let mut roots = Vec::with_capacity(batch);
+    for first in (0..subtrees).step_by(batch) {
+        let last = subtrees.min(first + batch);
+        (first..last)
+            .into_par_iter()
+            .map(|i| {
+                let start = i << height;
+                let end = leaves.min(start + (1 << height));
+                subtree_root::<H>(cache, start, end, height)
+            })
+            .collect_into_vec(&mut roots);
+        roots.iter().copied().for_each(&mut f);
+    }
+}
+
+/// The upper levels of the dataset tree of an epoch, from the nodes
+/// [`CACHE_LEVEL`] levels below the root up to the root.
+///
+/// With the light cache, they are enough to prove any element of the dataset:
+/// the element and the subtree under its cached node are recomputed from the
+/// cache. The cached nodes are `2^CACHE_LEVEL` hashes (512 KiB), and can be
+/// stored with [`CachedLevels::to_bytes`] instead of the dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedLevels<H: MerkleHasher = Sha256Hasher> {
+    epoch: usize,
+    depth: usize,
+    // Number of (non-zero) leaves of the dataset tree.
+    leaves: usize,
+    // Height of the cached nodes, i.e. of the recomputed subtrees.
+    height: usize,
+    // `levels[0]` are the cached nodes, the last level holds the root only.
+    levels: Vec<Vec<Hash>>,
+    // `zero_hashes[d]` is the hash of a zero subtree of height `d`.
+    zero_hashes: Vec<Hash>,
+    hasher: PhantomData<H>,
+}
+
+impl CachedLevels {
+    /// Compute the cached levels of `epoch` from its light cache.
+    pub fn new(epoch: usize, cache: &[u8]) -> Self {
+        Self::with_hasher(epoch, cache)
+    }
+}
+
+impl<H: MerkleHasher> CachedLevels<H> {
+    /// Compute the cached levels of `epoch` from its light cache, for a tree
+    /// hashed with `H`.
+    pub fn with_hasher(epoch: usize, cache: &[u8]) -> Self {
+        let (leaves, depth) = dataset_shape(epoch);
+        let height = cached_height(depth);
+        let mut nodes = Vec::with_capacity(leaves.div_ceil(1 << height));
+        for_each_subtree_root::<H, _>(cache, leaves, height, |root| {
+            nodes.push(root)
+        });
+        Self::build(epoch, leaves, depth, height, nodes)
+    }
+
+    /// Rebuild the cached levels of `epoch` from the cached nodes, as
+    /// returned by [`CachedLevels::nodes`].
+    pub fn from_nodes(
+        epoch: usize,
+        nodes: Vec<Hash>,
+    ) -> Result<Self, MerkleTreeError> {
+        let (leaves, depth) = dataset_shape(epoch);
+        let height = cached_height(depth);
+        if nodes.len() != leaves.div_ceil(1 << height) {
+            return Err(MerkleTreeError::Invalid);
+        }
+        Ok(Self::build(epoch, leaves, depth, height, nodes))
+    }
+
+    /// Parse the output of [`CachedLevels::to_bytes`].
+    pub fn from_bytes(
+        epoch: usize,
+        bytes: &[u8],
+    ) -> Result<Self, MerkleTreeError> {
+        if !bytes.len().is_multiple_of(HASH_LENGTH) {
+            return Err(MerkleTreeError::Invalid);
+        }
+        let nodes = bytes.chunks_exact(HASH_LENGTH).map(Hash::from).collect();
+        Self::from_nodes(epoch, nodes)
+    }
+
+    /// The cached nodes, one after the other.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.nodes().iter().flat_map(|h| h.0).collect()
+    }
+
+    fn build(
+        epoch: usize,
+        leaves: usize,
+        depth: usize,
+        height: usize,
+        nodes: Vec<Hash>,
+    ) -> Self {
+        let zero_hashes = H::zero_hashes(depth);
+        let mut levels = vec![nodes];
+        for h in height..depth {
+            let children = &levels[h - height];
+            let parents = mtree::hash_level::<H>(children, &zero_hashes[h]);
+            levels.push(parents);
+        }
+        Self {
+            epoch,
+            depth,
+            leaves,
+            height,
+            levels,
+            zero_hashes,
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn epoch(&self) -> usize { self.epoch }
+
+    pub fn depth(&self) -> usize { self.depth }
+
+    /// Height of the cached nodes above the leaves.
+    pub fn height(&self) -> usize { self.height }
+
+    /// The cached nodes, from left to right.
+    pub fn nodes(&self) -> &[Hash] { &self.levels[0] }
+
+    /// Root hash of the dataset tree.
+    pub fn hash(&self) -> Hash { self.node(self.depth, 0) }
+
+    /// Hash of the node at `index` among the nodes at `height`, which must
+    /// not be below the cached nodes.
+    pub fn node(&self, height: usize, index: usize) -> Hash {
+        self.levels[height - self.height]
+            .get(index)
+            .copied()
+            .unwrap_or(self.zero_hashes[height])
+    }
+
+    /// Return the element at `index` and a Merkle proof of its inclusion,
+    /// recomputing the element and its subtree from the light `cache`.
+    ///
+    /// Same output as [`MerkleTree::generate_proof`](mtree::MerkleTree) on
+    /// the tree of the whole dataset.
+    pub fn generate_proof(
+        &self,
+        cache: &[u8],
+        index: usize,
+    ) -> (Word, Hash, Vec<Hash>) {
+        assert!(index < self.leaves, "index must point to a non-zero leaf");
+        let start = index >> self.height << self.height;
+        let end = self.leaves.min(start + (1 << self.height));
+        let word = dataset_word(cache, index);
+        let mut level: Vec<Hash> = (start..end)
+            .map(|i| {
+                if i == index {
+                    H::hash_element(&word)
+                } else {
+                    H::hash_element(&dataset_word(cache, i))
+                }
+            })
+            .collect();
+        let leaf = level[index - start];
+
+        let mut proof = Vec::with_capacity(self.depth);
+        for height in 0..self.height {
+            let sibling = ((index - start) >> height) ^ 1;
+            let node = level.get(sibling).copied();
+            let zero = self.zero_hashes[height];
+            proof.push(node.unwrap_or(zero));
+            level = mtree::hash_level::<H>(&level, &zero);
+        }
+        for height in self.height..self.depth {
+            proof.push(self.node(height, (index >> height) ^ 1));
+        }
+        (word, leaf, proof)
+    }
+}
+
+impl BlockWithProofs {
+    /// Same as [`BlockWithProofs::from_tree`], with the elements and their
+    /// proofs recomputed from the light `cache` of the epoch of `levels`.
+    pub fn from_cache<H: MerkleHasher>(
+        header_rlp: Vec<u8>,
+        levels: &CachedLevels<H>,
+        cache: &[u8],
+        indices: &[u32],
+    ) -> Self {
+        let depth = levels.depth();
+        let mut elements = Vec::with_capacity(indices.len() * 4);
+        let mut merkle_proofs = Vec::with_capacity(indices.len() * depth);
+        for index in indices {
+            let (word, _, proof) = levels.generate_proof(cache, *index as _);
+            elements.extend(word.to_words::<H::Encoding>());
+            merkle_proofs.extend(proof);
+        }
+        Self {
+            proof_length: depth as u64,
+            header_rlp,
+            merkle_root: levels.hash(),
+            elements,
+            merkle_proofs,
+        }
+    }
+}
+
+/// Number of leaves and depth of the dataset tree of `epoch`.
+fn dataset_shape(epoch: usize) -> (usize, usize) {
+    let leaves = crate::get_full_size(epoch) / WORD_LENGTH;
+    (leaves, super::calc_dataset_depth(epoch))
+}
+
+/// Height of the nodes [`CACHE_LEVEL`] levels below the root.
+fn cached_height(depth: usize) -> usize {
+    depth.saturating_sub(CACHE_LEVEL as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mtree::{DobuleLeaf, MerkleTree};
+    use super::*;
+
+    const LEAVES: usize = 37;
+    const DEPTH: usize = 6;
+
+    fn cache_and_tree() -> (Vec<u8>, MerkleTree) {
+        let mut cache = vec![0u8; 64 * 31];
+        crate::make_cache(&mut cache, crate::get_seedhash(0));
+        let mut dataset = vec![0u8; LEAVES * WORD_LENGTH];
+        crate::make_dataset(&mut dataset, &cache);
+
+        let leaves: Vec<DobuleLeaf> = dataset
+            .chunks_exact(WORD_LENGTH)
+            .map(|chunk| DobuleLeaf::new(Word::from(chunk)))
+            .collect();
+        (cache, MerkleTree::create(leaves, DEPTH))
+    }
+
+    #[test]
+    fn root_matches_dataset_tree() {
+        let (cache, tree) = cache_and_tree();
+        assert_eq!(dataset_word(&cache, 5), tree.leaf(5).unwrap().word);
+        for height in [0, 2, 3, 6, 8] {
+            assert_eq!(
+                stream_root::<Sha256Hasher>(&cache, LEAVES, DEPTH, height),
+                tree.hash(),
+                "subtrees of height {}",
+                height
+            );
+        }
+    }
+
+    #[test]
+    fn proofs_match_dataset_tree() {
+        let (cache, tree) = cache_and_tree();
+        for height in [0, 2, 3, 6] {
+            let mut nodes = Vec::new();
+            for_each_subtree_root::<Sha256Hasher, _>(
+                &cache,
+                LEAVES,
+                height,
+                |root| nodes.push(root),
+            );
+            let levels = CachedLevels::<Sha256Hasher>::build(
+                0, LEAVES, DEPTH, height, nodes,
+            );
+            assert_eq!(levels.hash(), tree.hash());
+            for index in 0..LEAVES {
+                assert_eq!(
+                    levels.generate_proof(&cache, index),
+                    tree.proof(index),
+                    "index {} below height {}",
+                    index,
+                    height
+                );
+            }
+
+            let indices = [3, 36, 0, 17];
+            assert_eq!(
+                BlockWithProofs::from_cache(
+                    vec![0xc0],
+                    &levels,
+                    &cache,
+                    &indices
+                ),
+                BlockWithProofs::from_tree(vec![0xc0], &tree, &indices)
+            );
+        }
+        assert_eq!(
+            CachedLevels::<Sha256Hasher>::from_bytes(0, &[0; 17]),
+            Err(MerkleTreeError::Invalid)
+        );
+        assert_eq!(
+            CachedLevels::<Sha256Hasher>::from_nodes(0, vec![Hash::zero()]),
+            Err(MerkleTreeError::Invalid)
+        );
+    }
+}
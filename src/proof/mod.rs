@@ -13,8 +13,26 @@ pub const HASH_LENGTH: usize = 16;
 pub const WORD_LENGTH: usize = 128;
 pub const BRANCH_ELEMENT_LENGTH: usize = 32;
 
+pub mod abi;
+#[cfg(feature = "std")]
+pub mod builder;
+pub mod chain;
+pub mod devnet;
+pub mod difficulty;
+pub mod encoding;
+pub mod light;
 pub mod mtree;
+pub mod roots;
+#[cfg(feature = "json")]
+pub mod rpc;
+pub mod seal;
+#[cfg(feature = "serde")]
+pub mod serde_hex;
+pub mod spec;
 pub mod types;
+pub mod uncles;
+pub mod verify;
+pub mod weight;
 
 pub fn keccak_512(data: &[u8]) -> [u8; 64] {
     let mut keccak = Keccak::v512();
@@ -75,9 +93,17 @@ where
 
 /// A conventional way for calculating the Root hash of the merkle tree.
 pub fn calc_dataset_merkle_root(epoch: usize, dataset: &[u8]) -> H128 {
-    let (depth, leaves) = calc_dataset_merkle_leaves(epoch, dataset);
-    let leaves: Vec<&mtree::DobuleLeaf> = leaves.iter().collect();
-    let tree = mtree::MerkleTree::create(&leaves, depth);
+    calc_dataset_merkle_root_with_hasher::<mtree::Sha256Hasher>(epoch, dataset)
+}
+
+/// Same as [`calc_dataset_merkle_root`], for a tree hashed with `H`.
+pub fn calc_dataset_merkle_root_with_hasher<H: mtree::MerkleHasher>(
+    epoch: usize,
+    dataset: &[u8],
+) -> H128 {
+    let (depth, leaves) =
+        calc_dataset_merkle_leaves_with_hasher::<H>(epoch, dataset);
+    let tree = mtree::MerkleTree::create(leaves, depth);
     let root = tree.hash();
     H128::from_slice(&root.0)
 }
@@ -94,13 +120,24 @@ pub fn calc_dataset_merkle_leaves(
     epoch: usize,
     dataset: &[u8],
 ) -> (usize, Vec<mtree::DobuleLeaf>) {
+    calc_dataset_merkle_leaves_with_hasher(epoch, dataset)
+}
+
+/// Same as [`calc_dataset_merkle_leaves`], for a tree hashed with `H`.
+pub fn calc_dataset_merkle_leaves_with_hasher<H: mtree::MerkleHasher>(
+    epoch: usize,
+    dataset: &[u8],
+) -> (usize, Vec<mtree::DobuleLeaf<H>>) {
     let branch_depth = calc_dataset_depth(epoch);
     let leaves = dataset_leaves(epoch, dataset);
     (branch_depth, leaves)
 }
 
 #[cfg(not(feature = "std"))]
-fn dataset_leaves(epoch: usize, dataset: &[u8]) -> Vec<mtree::DobuleLeaf> {
+fn dataset_leaves<H: mtree::MerkleHasher>(
+    epoch: usize,
+    dataset: &[u8],
+) -> Vec<mtree::DobuleLeaf<H>> {
     let full_size = crate::get_full_size(epoch);
     let full_size_128_resolution = full_size / 128;
     let mut leaves = Vec::with_capacity(full_size_128_resolution);
@@ -109,14 +146,17 @@ fn dataset_leaves(epoch: usize, dataset: &[u8]) -> Vec<mtree::DobuleLeaf> {
         let mut buf = [0u8; 128];
         buf.copy_from_slice(chunk);
         let word = mtree::Word(buf);
-        let leaf = mtree::DobuleLeaf::new(word);
+        let leaf = mtree::DobuleLeaf::with_hasher(word);
         leaves.push(leaf);
     }
     leaves
 }
 
 #[cfg(feature = "std")]
-fn dataset_leaves(epoch: usize, dataset: &[u8]) -> Vec<mtree::DobuleLeaf> {
+fn dataset_leaves<H: mtree::MerkleHasher>(
+    epoch: usize,
+    dataset: &[u8],
+) -> Vec<mtree::DobuleLeaf<H>> {
     use rayon::prelude::*;
     let _ = epoch;
     // setup rayon thread pool.
@@ -131,7 +171,7 @@ fn dataset_leaves(epoch: usize, dataset: &[u8]) -> Vec<mtree::DobuleLeaf> {
             let mut buf = [0u8; 128];
             buf.copy_from_slice(chunk);
             let word = mtree::Word(buf);
-            mtree::DobuleLeaf::new(word)
+            mtree::DobuleLeaf::with_hasher(word)
         })
         .collect();
     leaves
@@ -1,24 +1,19 @@
-use core::convert::TryInto;
+use core::cmp::Ordering;
 use core::ops::Deref;
-use alloc::{boxed::Box, vec, vec::Vec};
+use alloc::{vec, vec::Vec};
+use core::marker::PhantomData;
 
 use ethereum_types::H256;
 use lazy_static::lazy_static;
 use sha2::Digest;
 
+use super::encoding::{Conventional, ElementEncoding};
+
 const HASH_LENGTH: usize = 16; // bytes.
 const WORD_LENGTH: usize = 128; // bytes.
-const BRANCH_ELEMENT_LENGTH: usize = 32; // bytes.
-const MAX_TREE_DEPTH: usize = 32;
-const EMPTY_SLICE: &[&DobuleLeaf] = &[];
 const ZERO_HASHES_MAX_INDEX: usize = 48;
 
 lazy_static! {
-    /// Zero nodes to act as "synthetic" left and right subtrees of other zero nodes.
-    static ref ZERO_NODES: Vec<MerkleTree<'static>> = {
-        (0..=MAX_TREE_DEPTH).map(MerkleTree::Zero).collect()
-    };
-
     /// Cached zero hashes where `ZERO_HASHES[i]` is the hash of a Merkle tree with 2^i zero leaves.
     pub static ref ZERO_HASHES: Vec<Hash> = {
         let mut hashes = vec![Hash::zero(); ZERO_HASHES_MAX_INDEX + 1];
@@ -36,24 +31,25 @@ pub struct Hash(pub [u8; HASH_LENGTH]);
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Word(pub [u8; WORD_LENGTH]);
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(super) struct BranchElement(pub [u8; BRANCH_ELEMENT_LENGTH]);
-
 impl Hash {
     pub fn zero() -> Self { Self([0u8; HASH_LENGTH]) }
 }
 
 impl Word {
-    pub fn into_h256_array(mut self) -> [H256; 4] {
-        self.0
-            .chunks_exact_mut(32)
-            .map(|s| {
-                s.reverse();
-                H256::from_slice(s)
-            })
-            .collect::<Vec<_>>()
-            .try_into()
-            .expect("hash to H256 should never fails")
+    /// The element as four `uint256` words in the
+    /// [conventional](Word::conventional) encoding.
+    pub fn into_h256_array(self) -> [H256; 4] {
+        self.to_words::<Conventional>()
+    }
+
+    /// The element as four 256-bit words in the encoding `E`.
+    pub fn to_words<E: ElementEncoding>(&self) -> [H256; 4] {
+        E::to_words(self)
+    }
+
+    /// Inverse of [`Word::to_words`].
+    pub fn from_words<E: ElementEncoding>(words: &[H256; 4]) -> Self {
+        E::from_words(words)
     }
 
     /// #### Conventional encoding
@@ -70,12 +66,11 @@ impl Word {
     pub fn conventional(&self) -> ([u8; 64], [u8; 64]) {
         let mut first = [0u8; 64];
         let mut second = [0u8; 64];
-        self.0
-            .clone()
-            .chunks_exact_mut(32)
+        let mut word = self.0;
+        word.chunks_exact_mut(32)
             .map(|c| {
                 c.reverse();
-                c
+                &*c
             })
             .enumerate()
             .for_each(|(i, chunk)| match i {
@@ -97,7 +92,7 @@ impl<'a> From<&'a [u8]> for Hash {
     fn from(b: &'a [u8]) -> Self {
         assert_eq!(b.len(), HASH_LENGTH);
         let mut inner = [0u8; HASH_LENGTH];
-        inner.copy_from_slice(&b);
+        inner.copy_from_slice(b);
         Self(inner)
     }
 }
@@ -110,24 +105,11 @@ impl<'a> From<&'a [u8]> for Word {
     fn from(b: &'a [u8]) -> Self {
         assert_eq!(b.len(), WORD_LENGTH);
         let mut inner = [0u8; WORD_LENGTH];
-        inner.copy_from_slice(&b);
+        inner.copy_from_slice(b);
         Self(inner)
     }
 }
 
-impl From<[u8; BRANCH_ELEMENT_LENGTH]> for BranchElement {
-    fn from(b: [u8; BRANCH_ELEMENT_LENGTH]) -> Self { Self(b) }
-}
-
-impl From<[Hash; 2]> for BranchElement {
-    fn from([h1, h2]: [Hash; 2]) -> Self {
-        let mut b: [u8; BRANCH_ELEMENT_LENGTH] = [0; BRANCH_ELEMENT_LENGTH];
-        b[0..16].copy_from_slice(h1.deref());
-        b[16..32].copy_from_slice(h2.deref());
-        Self(b)
-    }
-}
-
 impl From<[H256; 4]> for Word {
     fn from(b: [H256; 4]) -> Self {
         let mut inner = [0u8; WORD_LENGTH];
@@ -139,8 +121,8 @@ impl From<[H256; 4]> for Word {
     }
 }
 
-impl Into<[H256; 4]> for Word {
-    fn into(self) -> [H256; 4] { self.into_h256_array() }
+impl From<Word> for [H256; 4] {
+    fn from(word: Word) -> Self { word.into_h256_array() }
 }
 
 impl Deref for Hash {
@@ -155,12 +137,6 @@ impl Deref for Word {
     fn deref(&self) -> &Self::Target { &self.0 }
 }
 
-impl Deref for BranchElement {
-    type Target = [u8; BRANCH_ELEMENT_LENGTH];
-
-    fn deref(&self) -> &Self::Target { &self.0 }
-}
-
 pub(super) fn hash(a: &Hash, b: &Hash) -> Hash {
     let hasher = sha2::Sha256::default();
     let hash = hasher
@@ -174,30 +150,98 @@ pub(super) fn hash(a: &Hash, b: &Hash) -> Hash {
     Hash(data)
 }
 
-pub(super) fn hash_element(word: &Word) -> Hash {
-    let (first, second) = word.conventional();
-    let hasher = sha2::Sha256::default();
-    let hash = hasher.chain(first).chain(second).result();
-    let mut data = [0u8; HASH_LENGTH];
-    data.copy_from_slice(&hash[HASH_LENGTH..]);
-    Hash(data)
+/// Hash function of the nodes and leaves of the dataset Merkle tree.
+///
+/// The tree code is generic over it so that roots and proofs can be produced
+/// for whichever hash function is cheap on the verifying chain.
+pub trait MerkleHasher: Send + Sync {
+    /// Layout of the elements fed to [`MerkleHasher::hash_leaf`].
+    type Encoding: ElementEncoding;
+
+    /// Hash of an internal node given its left and right children.
+    fn hash_nodes(left: &Hash, right: &Hash) -> Hash;
+
+    /// Hash of a leaf given its encoded element.
+    fn hash_leaf(data: &[u8; WORD_LENGTH]) -> Hash;
+
+    /// Hash of a leaf given the dataset element it holds.
+    fn hash_element(word: &Word) -> Hash {
+        Self::hash_leaf(&Self::Encoding::encode(word))
+    }
+
+    /// Hashes of the zero subtrees up to `depth`: the `d`-th one is the hash
+    /// of a tree of `2^d` zero leaves. Trees compute them once and look them
+    /// up.
+    fn zero_hashes(depth: usize) -> Vec<Hash> {
+        let mut hashes = vec![Hash::zero(); depth + 1];
+        for d in 0..depth {
+            hashes[d + 1] = Self::hash_nodes(&hashes[d], &hashes[d]);
+        }
+        hashes
+    }
+}
+
+/// The default scheme: SHA-256 over the children each left-padded with 16
+/// zero bytes, and over the elements encoded with `E` (by default the
+/// [conventional](Word::conventional) encoding), keeping the low 16 bytes of
+/// the digest.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Sha256Hasher<E = Conventional>(PhantomData<E>);
+
+impl<E: ElementEncoding> MerkleHasher for Sha256Hasher<E> {
+    type Encoding = E;
+
+    fn hash_nodes(left: &Hash, right: &Hash) -> Hash { hash(left, right) }
+
+    fn hash_leaf(data: &[u8; WORD_LENGTH]) -> Hash {
+        let hash = sha2::Sha256::digest(data);
+        Hash::from(&hash[HASH_LENGTH..])
+    }
+
+    fn zero_hashes(depth: usize) -> Vec<Hash> {
+        let cached = depth.min(ZERO_HASHES_MAX_INDEX);
+        let mut hashes = ZERO_HASHES[..=cached].to_vec();
+        while hashes.len() <= depth {
+            let last = hashes[hashes.len() - 1];
+            hashes.push(hash(&last, &last));
+        }
+        hashes
+    }
+}
+
+/// Same layout as [`Sha256Hasher`], with Keccak-256 as the hash function.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Keccak256Hasher<E = Conventional>(PhantomData<E>);
+
+impl<E: ElementEncoding> MerkleHasher for Keccak256Hasher<E> {
+    type Encoding = E;
+
+    fn hash_nodes(left: &Hash, right: &Hash) -> Hash {
+        let mut data = [0u8; 64];
+        data[16..32].copy_from_slice(&left.0);
+        data[48..64].copy_from_slice(&right.0);
+        Hash::from(&super::keccak_256(&data)[HASH_LENGTH..])
+    }
+
+    fn hash_leaf(data: &[u8; WORD_LENGTH]) -> Hash {
+        Hash::from(&super::keccak_256(data)[HASH_LENGTH..])
+    }
 }
 
 /// Right-sparse Merkle tree.
 ///
 /// Efficiently represents a Merkle tree of fixed depth where only the first N
 /// indices are populated by non-zero leaves (perfect for the deposit contract
-/// tree).
-#[derive(Debug, PartialEq)]
-pub enum MerkleTree<'a> {
-    /// Leaf node with the hash of its content.
-    Leaf(&'a DobuleLeaf),
-    /// Internal node with hash, left subtree and right subtree.
-    Node(Hash, Box<Self>, Box<Self>),
-    /// Zero subtree of a given depth.
-    ///
-    /// It represents a Merkle tree of 2^depth zero leaves.
-    Zero(usize),
+/// tree). The tree owns its leaves and keeps the hashes of every level in a
+/// vector, missing right subtrees standing for zero subtrees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree<H: MerkleHasher = Sha256Hasher> {
+    depth: usize,
+    leaves: Vec<DobuleLeaf<H>>,
+    // `levels[d - 1]` holds the hashes of the non-zero nodes at height `d`.
+    levels: Vec<Vec<Hash>>,
+    // `zero_hashes[d]` is the hash of a zero subtree of height `d`.
+    zero_hashes: Vec<Hash>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -214,171 +258,235 @@ pub enum MerkleTreeError {
     ArithError,
 }
 
-impl<'a> MerkleTree<'a> {
+impl<H: MerkleHasher> MerkleTree<H> {
     /// Create a new Merkle tree from a list of leaves and a fixed depth.
-    pub fn create(leaves: &[&'a DobuleLeaf], depth: usize) -> Self {
-        use MerkleTree::*;
-
-        if leaves.is_empty() {
-            return Zero(depth);
+    ///
+    /// With the `std` feature, the nodes of each level are hashed in parallel.
+    pub fn create(leaves: Vec<DobuleLeaf<H>>, depth: usize) -> Self {
+        assert!(
+            depth < usize::BITS as usize && leaves.len() <= 1 << depth,
+            "too many leaves for a tree of depth {}",
+            depth
+        );
+        let zero_hashes = H::zero_hashes(depth);
+        let hashes: Vec<Hash> = leaves.iter().map(|l| l.hash).collect();
+        let mut levels: Vec<Vec<Hash>> = Vec::with_capacity(depth);
+        for zero in &zero_hashes[..depth] {
+            let children = levels.last().unwrap_or(&hashes);
+            levels.push(hash_level::<H>(children, zero));
         }
-
-        match depth {
-            0 => {
-                debug_assert_eq!(leaves.len(), 1);
-                Leaf(leaves[0])
-            },
-            _ => {
-                // Split leaves into left and right subtrees
-                let subtree_capacity = 2usize.pow(depth as u32 - 1);
-                let (left_leaves, right_leaves) =
-                    if leaves.len() <= subtree_capacity {
-                        (leaves, EMPTY_SLICE)
-                    } else {
-                        leaves.split_at(subtree_capacity)
-                    };
-
-                let left_subtree = MerkleTree::create(left_leaves, depth - 1);
-                let right_subtree = MerkleTree::create(right_leaves, depth - 1);
-                let h = hash(&left_subtree.hash(), &right_subtree.hash());
-
-                Node(h, Box::new(left_subtree), Box::new(right_subtree))
-            },
+        Self {
+            depth,
+            leaves,
+            levels,
+            zero_hashes,
         }
     }
 
     /// Push an element in the MerkleTree.
-    /// MerkleTree and depth must be correct, as the algorithm expects valid
-    /// data.
+    pub fn push(&mut self, elem: DobuleLeaf<H>) -> Result<(), MerkleTreeError> {
+        if self.leaves.len() == 1 << self.depth {
+            return Err(MerkleTreeError::MerkleTreeFull);
+        }
+        let index = self.leaves.len();
+        self.leaves.push(elem);
+        for height in 1..=self.depth {
+            let i = index >> height;
+            let h = H::hash_nodes(
+                &self.node(height - 1, 2 * i),
+                &self.node(height - 1, 2 * i + 1),
+            );
+            let level = &mut self.levels[height - 1];
+            match level.get_mut(i) {
+                Some(node) => *node = h,
+                None => level.push(h),
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as [`MerkleTree::push`], checking that `depth` is the depth of
+    /// the tree.
     pub fn push_leaf(
         &mut self,
-        elem: &'a DobuleLeaf,
+        elem: DobuleLeaf<H>,
         depth: usize,
     ) -> Result<(), MerkleTreeError> {
-        use MerkleTree::*;
-
-        if depth == 0 {
-            return Err(MerkleTreeError::DepthTooSmall);
+        match depth.cmp(&self.depth) {
+            Ordering::Less => Err(MerkleTreeError::DepthTooSmall),
+            Ordering::Greater => Err(MerkleTreeError::Invalid),
+            Ordering::Equal => self.push(elem),
         }
-
-        match self {
-            Leaf(_) => return Err(MerkleTreeError::LeafReached),
-            Zero(_) => {
-                *self = MerkleTree::create(&[elem], depth);
-            },
-            Node(ref mut h, ref mut left, ref mut right) => {
-                let left: &mut MerkleTree = &mut *left;
-                let right: &mut MerkleTree = &mut *right;
-                match (&*left, &*right) {
-                    // Tree is full
-                    (Leaf(_), Leaf(_)) => {
-                        return Err(MerkleTreeError::MerkleTreeFull)
-                    },
-                    // There is a right node so insert in right node
-                    (Node(_, _, _), Node(_, _, _)) => {
-                        if let Err(e) = right.push_leaf(elem, depth - 1) {
-                            return Err(e);
-                        }
-                    },
-                    // Both branches are zero, insert in left one
-                    (Zero(_), Zero(_)) => {
-                        *left = MerkleTree::create(&[elem], depth - 1);
-                    },
-                    // Leaf on left branch and zero on right branch, insert on
-                    // right side
-                    (Leaf(_), Zero(_)) => {
-                        *right = MerkleTree::create(&[elem], depth - 1);
-                    },
-                    // Try inserting on the left node -> if it fails because it
-                    // is full, insert in right side.
-                    (Node(_, _, _), Zero(_)) => {
-                        match left.push_leaf(elem, depth - 1) {
-                            Ok(_) => (),
-                            // Left node is full, insert in right node
-                            Err(MerkleTreeError::MerkleTreeFull) => {
-                                *right = MerkleTree::create(&[elem], depth - 1);
-                            },
-                            Err(e) => return Err(e),
-                        };
-                    },
-                    // All other possibilities are invalid MerkleTrees
-                    (_, _) => return Err(MerkleTreeError::Invalid),
-                };
-                *h = hash(&left.hash(), &right.hash());
-            },
-        }
-
-        Ok(())
     }
 
     /// Retrieve the root hash of this Merkle tree.
-    pub fn hash(&self) -> Hash {
-        match *self {
-            MerkleTree::Leaf(ref h) => h.hash,
-            MerkleTree::Node(h, _, _) => h,
-            MerkleTree::Zero(depth) => ZERO_HASHES[depth],
-        }
-    }
+    pub fn hash(&self) -> Hash { self.node(self.depth, 0) }
 
-    /// Retrieve the value [`Word`] of this Merkle tree (if any).
-    pub fn value(&self) -> Option<Word> {
-        match *self {
-            MerkleTree::Leaf(ref h) => Some(h.word.clone()),
-            MerkleTree::Node(_, _, _) => None,
-            MerkleTree::Zero(_) => None,
-        }
-    }
+    pub fn depth(&self) -> usize { self.depth }
 
-    /// Get a reference to the left and right subtrees if they exist.
-    pub fn left_and_right_branches(&self) -> Option<(&Self, &Self)> {
-        match *self {
-            MerkleTree::Leaf(_) | MerkleTree::Zero(0) => None,
-            MerkleTree::Node(_, ref l, ref r) => Some((l, r)),
-            MerkleTree::Zero(depth) => {
-                Some((&ZERO_NODES[depth - 1], &ZERO_NODES[depth - 1]))
-            },
-        }
+    /// Number of (non-zero) leaves.
+    pub fn len(&self) -> usize { self.leaves.len() }
+
+    pub fn is_empty(&self) -> bool { self.leaves.is_empty() }
+
+    pub fn leaves(&self) -> &[DobuleLeaf<H>] { &self.leaves }
+
+    /// Retrieve the leaf at `index` (if any).
+    pub fn leaf(&self, index: usize) -> Option<&DobuleLeaf<H>> {
+        self.leaves.get(index)
     }
 
-    /// Is this Merkle tree a leaf?
-    pub fn is_leaf(&self) -> bool { matches!(self, MerkleTree::Leaf(_)) }
+    /// Hash of the node at `index` among the nodes at `height` (leaves being
+    /// at height 0).
+    pub fn node(&self, height: usize, index: usize) -> Hash {
+        let node = match height {
+            0 => self.leaves.get(index).map(|l| l.hash),
+            _ => self.levels[height - 1].get(index).copied(),
+        };
+        node.unwrap_or(self.zero_hashes[height])
+    }
 
     /// Return the leaf at `index` and a Merkle proof of its inclusion.
     ///
     /// The Merkle proof is in "bottom-up" order, starting with a leaf node
-    /// and moving up the tree. Its length will be exactly equal to `depth`.
+    /// and moving up the tree. Its length will be exactly equal to the depth
+    /// of the tree.
+    pub fn proof(&self, index: usize) -> (Word, Hash, Vec<Hash>) {
+        let leaf = self
+            .leaf(index)
+            .expect("index must point to a non-zero leaf");
+        let proof = (0..self.depth)
+            .map(|height| self.node(height, (index >> height) ^ 1))
+            .collect();
+        (leaf.word.clone(), leaf.hash, proof)
+    }
+
+    /// Same as [`MerkleTree::proof`], for a tree of depth `depth`.
     pub fn generate_proof(
         &self,
         index: usize,
         depth: usize,
     ) -> (Word, Hash, Vec<Hash>) {
-        let mut proof = vec![];
-        let mut current_node = self;
-        let mut current_depth = depth;
-        while current_depth > 0 {
-            let ith_bit = (index >> (current_depth - 1)) & 0x01;
-            // Note: unwrap is safe because leaves are only ever constructed at
-            // depth == 0.
-            let (left, right) = current_node.left_and_right_branches().unwrap();
-
-            // Go right, include the left branch in the proof.
-            if ith_bit == 1 {
-                proof.push(left.hash());
-                current_node = right;
-            } else {
-                proof.push(right.hash());
-                current_node = left;
-            }
-            current_depth -= 1;
+        assert_eq!(depth, self.depth, "depth must be the one of the tree");
+        self.proof(index)
+    }
+}
+
+/// Hash pairs of nodes into the level above, pairing a missing right sibling
+/// with `zero`, the hash of a zero subtree of the height of the nodes.
+#[cfg(not(feature = "std"))]
+pub(super) fn hash_level<H: MerkleHasher>(
+    nodes: &[Hash],
+    zero: &Hash,
+) -> Vec<Hash> {
+    nodes
+        .chunks(2)
+        .map(|pair| hash_pair::<H>(pair, zero))
+        .collect()
+}
+
+#[cfg(feature = "std")]
+pub(super) fn hash_level<H: MerkleHasher>(
+    nodes: &[Hash],
+    zero: &Hash,
+) -> Vec<Hash> {
+    use rayon::prelude::*;
+
+    nodes
+        .par_chunks(2)
+        .map(|pair| hash_pair::<H>(pair, zero))
+        .collect()
+}
+
+fn hash_pair<H: MerkleHasher>(pair: &[Hash], zero: &Hash) -> Hash {
+    match pair {
+        [left, right] => H::hash_nodes(left, right),
+        [left] => H::hash_nodes(left, zero),
+        _ => unreachable!("chunks of two"),
+    }
+}
+
+/// Root of a right-sparse Merkle tree whose nodes are fed from left to right.
+///
+/// Only the pending left sibling of every level is kept, so the root of a
+/// tree of any size is computed with `O(depth)` memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleStream<H: MerkleHasher = Sha256Hasher> {
+    depth: usize,
+    // Number of leaves covered by the nodes pushed so far.
+    len: usize,
+    // `pending[d]` is the left node at height `d` waiting for its sibling.
+    pending: Vec<Option<Hash>>,
+    // `zero_hashes[d]` is the hash of a zero subtree of height `d`.
+    zero_hashes: Vec<Hash>,
+    hasher: PhantomData<H>,
+}
+
+impl<H: MerkleHasher> MerkleStream<H> {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth < usize::BITS as usize, "depth {} is too big", depth);
+        Self {
+            depth,
+            len: 0,
+            pending: vec![None; depth + 1],
+            zero_hashes: H::zero_hashes(depth),
+            hasher: PhantomData,
         }
+    }
+
+    /// Number of leaves covered so far.
+    pub fn len(&self) -> usize { self.len }
 
-        debug_assert_eq!(proof.len(), depth);
-        debug_assert!(current_node.is_leaf());
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Push the hash of the next leaf.
+    pub fn push_leaf(&mut self, hash: Hash) -> Result<(), MerkleTreeError> {
+        self.push_node(0, hash)
+    }
 
-        // Put proof in bottom-up order.
-        proof.reverse();
+    /// Push the root of the next subtree of the given height, whose leaves
+    /// past the end of the tree are zero leaves.
+    ///
+    /// The leaves pushed so far must fill a whole number of such subtrees.
+    pub fn push_node(
+        &mut self,
+        height: usize,
+        hash: Hash,
+    ) -> Result<(), MerkleTreeError> {
+        if height > self.depth || !self.len.is_multiple_of(1 << height) {
+            return Err(MerkleTreeError::Invalid);
+        }
+        if self.len + (1 << height) > 1 << self.depth {
+            return Err(MerkleTreeError::MerkleTreeFull);
+        }
+        let (mut node, mut index) = (hash, self.len >> height);
+        let mut h = height;
+        while index & 1 == 1 {
+            let left = self.pending[h].take().expect("left sibling is pushed");
+            node = H::hash_nodes(&left, &node);
+            index >>= 1;
+            h += 1;
+        }
+        self.pending[h] = Some(node);
+        self.len += 1 << height;
+        Ok(())
+    }
 
-        (current_node.value().unwrap(), current_node.hash(), proof)
+    /// The root of the tree, padding it on the right with zero leaves.
+    pub fn root(&self) -> Hash {
+        let mut carry: Option<Hash> = None;
+        for height in 0..self.depth {
+            carry = match (self.pending[height], carry) {
+                (Some(left), Some(right)) => Some(H::hash_nodes(&left, &right)),
+                (Some(left), None) | (None, Some(left)) => {
+                    Some(H::hash_nodes(&left, &self.zero_hashes[height]))
+                },
+                (None, None) => None,
+            };
+        }
+        self.pending[self.depth]
+            .or(carry)
+            .unwrap_or(self.zero_hashes[self.depth])
     }
 }
 
@@ -394,17 +502,30 @@ pub fn verify_merkle_proof(
     depth: usize,
     index: usize,
     root: Hash,
+) -> bool {
+    verify_merkle_proof_with_hasher::<Sha256Hasher>(
+        element, branch, depth, index, root,
+    )
+}
+
+/// Same as [`verify_merkle_proof`], for a tree hashed with `H`.
+pub fn verify_merkle_proof_with_hasher<H: MerkleHasher>(
+    element: &Word,
+    branch: &[Hash],
+    depth: usize,
+    index: usize,
+    root: Hash,
 ) -> bool {
     if branch.len() == depth {
-        let leaf = hash_element(element);
-        merkle_root_from_branch(leaf, branch, depth, index) == root
+        let leaf = H::hash_element(element);
+        merkle_root_from_branch::<H>(leaf, branch, depth, index) == root
     } else {
         false
     }
 }
 
 /// Compute a root hash from a leaf and a Merkle proof.
-fn merkle_root_from_branch(
+pub(super) fn merkle_root_from_branch<H: MerkleHasher>(
     leaf: Hash,
     branch: &[Hash],
     depth: usize,
@@ -417,9 +538,9 @@ fn merkle_root_from_branch(
     for (i, leaf) in branch.iter().enumerate().take(depth) {
         let ith_bit = (index >> i) & 0x01;
         if ith_bit == 1 {
-            mroot = hash(&leaf, &mroot);
+            mroot = H::hash_nodes(leaf, &mroot);
         } else {
-            mroot = hash(&mroot, &leaf);
+            mroot = H::hash_nodes(&mroot, leaf);
         }
     }
     mroot
@@ -427,22 +548,32 @@ fn merkle_root_from_branch(
 
 /// Element that holds the actual data and it's hash.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct DobuleLeaf {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DobuleLeaf<H: MerkleHasher = Sha256Hasher> {
     pub hash: Hash,
     pub word: Word,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hasher: PhantomData<H>,
 }
 
 impl DobuleLeaf {
-    pub fn new(word: Word) -> Self {
+    pub fn new(word: Word) -> Self { Self::with_hasher(word) }
+}
+
+impl<H: MerkleHasher> DobuleLeaf<H> {
+    /// Create a leaf whose hash is computed by `H`.
+    pub fn with_hasher(word: Word) -> Self {
         Self {
-            hash: hash_element(&word),
+            hash: H::hash_element(&word),
             word,
+            hasher: PhantomData,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::encoding::{BigEndianWords, PackedU32, RawLittleEndian};
     use super::*;
 
     #[test]
@@ -467,4 +598,141 @@ mod tests {
         ];
         let _hashes = Word::from(word).into_h256_array();
     }
+
+    fn proofs_verify<H: MerkleHasher + Clone>() {
+        let depth = 3;
+        let leaves: Vec<DobuleLeaf<H>> = (0..5u8)
+            .map(|i| DobuleLeaf::with_hasher(Word([i; WORD_LENGTH])))
+            .collect();
+        let tree = MerkleTree::create(leaves.clone(), depth);
+        let root = tree.hash();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let (word, hash, proof) = tree.proof(index);
+            assert_eq!(word, leaf.word);
+            assert_eq!(hash, leaf.hash);
+            assert!(verify_merkle_proof_with_hasher::<H>(
+                &word, &proof, depth, index, root
+            ));
+            assert!(!verify_merkle_proof_with_hasher::<H>(
+                &word,
+                &proof,
+                depth,
+                index ^ 1,
+                root
+            ));
+        }
+    }
+
+    // The recursive construction of the tree, split in halves.
+    fn reference_root(leaves: &[Hash], depth: usize) -> Hash {
+        match (leaves, depth) {
+            ([], _) => ZERO_HASHES[depth],
+            ([leaf], 0) => *leaf,
+            _ => {
+                let half = 1 << (depth - 1);
+                let (left, right) = leaves.split_at(leaves.len().min(half));
+                hash(
+                    &reference_root(left, depth - 1),
+                    &reference_root(right, depth - 1),
+                )
+            },
+        }
+    }
+
+    #[test]
+    fn stream_matches_tree() {
+        let depth = 4;
+        for n in 0..=16u8 {
+            let leaves: Vec<DobuleLeaf> = (0..n)
+                .map(|i| DobuleLeaf::new(Word([i; WORD_LENGTH])))
+                .collect();
+            let hashes: Vec<Hash> = leaves.iter().map(|l| l.hash).collect();
+            let root = MerkleTree::create(leaves, depth).hash();
+
+            let mut stream = MerkleStream::<Sha256Hasher>::new(depth);
+            hashes.iter().for_each(|h| stream.push_leaf(*h).unwrap());
+            assert_eq!(stream.root(), root, "{} leaves", n);
+
+            // the same tree, fed with zero-padded subtrees of four leaves.
+            let mut stream = MerkleStream::<Sha256Hasher>::new(depth);
+            for chunk in hashes.chunks(4) {
+                stream.push_node(2, reference_root(chunk, 2)).unwrap();
+            }
+            assert_eq!(stream.root(), root, "{} leaves", n);
+        }
+        let mut stream = MerkleStream::<Sha256Hasher>::new(1);
+        stream.push_leaf(Hash::zero()).unwrap();
+        assert_eq!(
+            stream.push_node(1, Hash::zero()),
+            Err(MerkleTreeError::Invalid)
+        );
+        stream.push_leaf(Hash::zero()).unwrap();
+        assert_eq!(
+            stream.push_leaf(Hash::zero()),
+            Err(MerkleTreeError::MerkleTreeFull)
+        );
+    }
+
+    #[test]
+    fn create_and_push_leaf() {
+        let depth = 4;
+        let leaves: Vec<DobuleLeaf> = (0..16u8)
+            .map(|i| DobuleLeaf::new(Word([i; WORD_LENGTH])))
+            .collect();
+        let mut pushed = MerkleTree::create(Vec::new(), depth);
+        assert_eq!(pushed.hash(), ZERO_HASHES[depth]);
+        for n in 0..=leaves.len() {
+            let tree = MerkleTree::create(leaves[..n].to_vec(), depth);
+            let hashes: Vec<_> = leaves[..n].iter().map(|l| l.hash).collect();
+            assert_eq!(tree.hash(), reference_root(&hashes, depth));
+            assert_eq!(pushed, tree);
+            if n < leaves.len() {
+                pushed.push(leaves[n].clone()).unwrap();
+            }
+        }
+        assert_eq!(
+            pushed.push(leaves[0].clone()),
+            Err(MerkleTreeError::MerkleTreeFull)
+        );
+
+        let mut tree = MerkleTree::create(Vec::new(), depth);
+        assert_eq!(
+            tree.push_leaf(leaves[0].clone(), depth - 1),
+            Err(MerkleTreeError::DepthTooSmall)
+        );
+        assert_eq!(tree.push_leaf(leaves[0].clone(), depth), Ok(()));
+        assert_eq!(tree.generate_proof(0, depth), tree.proof(0));
+    }
+
+    #[test]
+    fn merkle_hashers() {
+        proofs_verify::<Sha256Hasher>();
+        proofs_verify::<Keccak256Hasher>();
+        proofs_verify::<Sha256Hasher<RawLittleEndian>>();
+        proofs_verify::<Keccak256Hasher<BigEndianWords>>();
+
+        let mut word = Word([0; WORD_LENGTH]);
+        word.0.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        let (first, second) = word.conventional();
+        let digest = sha2::Sha256::default()
+            .chain(first)
+            .chain(second)
+            .result();
+        let conventional = Hash::from(&digest[HASH_LENGTH..]);
+        assert_eq!(<Sha256Hasher>::hash_element(&word), conventional);
+        assert_ne!(
+            Sha256Hasher::<PackedU32>::hash_element(&word),
+            conventional
+        );
+
+        let folded = (0..5).fold(Hash::zero(), |h, _| hash(&h, &h));
+        assert_eq!(<Sha256Hasher>::zero_hashes(5)[5], folded);
+        assert_ne!(<Keccak256Hasher>::zero_hashes(5)[5], folded);
+        // past the static table of the SHA-256 hashes.
+        let max = ZERO_HASHES_MAX_INDEX;
+        let zero_hashes = <Sha256Hasher>::zero_hashes(max + 1);
+        assert_eq!(zero_hashes[..=max], ZERO_HASHES[..]);
+        let last = hash(&ZERO_HASHES[max], &ZERO_HASHES[max]);
+        assert_eq!(zero_hashes[max + 1], last);
+    }
 }
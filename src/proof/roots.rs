@@ -0,0 +1,378 @@
+//! Per-epoch DAG Merkle roots.
+//!
+//! Bridges need the dataset Merkle root of every epoch they are going to
+//! verify. [`EpochRoots`] holds such a table, reads and writes the
+//! `epoch:0xroot` line format produced by the `epoch` example (and JSON, with
+//! the `json` feature), and builds a [`RootsAccumulator`]: a Merkle tree over
+//! a contiguous range of roots, so a contract can be initialised with a single
+//! commitment and be handed the root of one epoch together with its inclusion
+//! proof later on.
+
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::{fmt, marker::PhantomData};
+
+use ethereum_types::H128;
+
+use super::mtree::{self, Hash, MerkleHasher, Sha256Hasher};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RootsError {
+    // Line (1-based) is not a valid `epoch:0xroot` entry
+    InvalidLine(usize),
+    // The same epoch appears more than once
+    DuplicateEpoch(usize),
+    // There are no roots in the table
+    Empty,
+    // The roots do not cover a contiguous range of epochs
+    MissingEpoch(usize),
+    // Malformed JSON document
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// A table of dataset Merkle roots, indexed by epoch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EpochRoots {
+    roots: BTreeMap<usize, H128>,
+}
+
+impl EpochRoots {
+    pub fn new() -> Self { Self::default() }
+
+    /// Record the root of `epoch`, returning the previous one if any.
+    pub fn insert(&mut self, epoch: usize, root: H128) -> Option<H128> {
+        self.roots.insert(epoch, root)
+    }
+
+    pub fn get(&self, epoch: usize) -> Option<H128> {
+        self.roots.get(&epoch).copied()
+    }
+
+    pub fn contains(&self, epoch: usize) -> bool {
+        self.roots.contains_key(&epoch)
+    }
+
+    pub fn len(&self) -> usize { self.roots.len() }
+
+    pub fn is_empty(&self) -> bool { self.roots.is_empty() }
+
+    /// Iterate over `(epoch, root)` pairs in ascending epoch order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, H128)> + '_ {
+        self.roots.iter().map(|(epoch, root)| (*epoch, *root))
+    }
+
+    pub fn first_epoch(&self) -> Option<usize> {
+        self.roots.keys().next().copied()
+    }
+
+    pub fn last_epoch(&self) -> Option<usize> {
+        self.roots.keys().next_back().copied()
+    }
+
+    /// Parse the `epoch:0xroot` line format. Blank lines are ignored, the
+    /// `0x` prefix is optional.
+    pub fn parse(s: &str) -> Result<Self, RootsError> {
+        let mut table = Self::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (epoch, root) =
+                parse_line(line).ok_or(RootsError::InvalidLine(i + 1))?;
+            if table.insert(epoch, root).is_some() {
+                return Err(RootsError::DuplicateEpoch(epoch));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Serialize into the `epoch:0xroot` line format, one epoch per line.
+    pub fn to_lines(&self) -> String { alloc::format!("{}", self) }
+
+    /// Serialize into a JSON array of `{"epoch": .., "root": "0x.."}`
+    /// objects.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        let entries: Vec<_> = self
+            .iter()
+            .map(|(epoch, root)| json::Entry {
+                epoch,
+                root: alloc::format!("{:?}", root),
+            })
+            .collect();
+        serde_json::to_string_pretty(&entries)
+            .expect("serializing plain structs never fails")
+    }
+
+    /// Parse the JSON produced by [`EpochRoots::to_json`].
+    #[cfg(feature = "json")]
+    pub fn from_json(s: &str) -> Result<Self, RootsError> {
+        let entries: Vec<json::Entry> =
+            serde_json::from_str(s).map_err(|_| RootsError::Json)?;
+        let mut table = Self::new();
+        for entry in entries {
+            let root = parse_root(&entry.root).ok_or(RootsError::Json)?;
+            if table.insert(entry.epoch, root).is_some() {
+                return Err(RootsError::DuplicateEpoch(entry.epoch));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Build the Merkle accumulator over all the roots in the table, which
+    /// must cover a contiguous range of epochs.
+    pub fn accumulator(&self) -> Result<RootsAccumulator, RootsError> {
+        self.accumulator_with_hasher()
+    }
+
+    /// Same as [`EpochRoots::accumulator`], for a tree hashed with `H`.
+    pub fn accumulator_with_hasher<H: MerkleHasher>(
+        &self,
+    ) -> Result<RootsAccumulator<H>, RootsError> {
+        let start_epoch = self.first_epoch().ok_or(RootsError::Empty)?;
+        let mut leaves = Vec::with_capacity(self.len());
+        for (i, (epoch, root)) in self.iter().enumerate() {
+            if epoch != start_epoch + i {
+                return Err(RootsError::MissingEpoch(start_epoch + i));
+            }
+            leaves.push(Hash::from(root.0));
+        }
+        Ok(RootsAccumulator::new(start_epoch, leaves))
+    }
+}
+
+impl fmt::Display for EpochRoots {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (epoch, root) in self.iter() {
+            writeln!(f, "{}:{:?}", epoch, root)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Option<(usize, H128)> {
+    let (epoch, root) = line.split_once(':')?;
+    Some((epoch.trim().parse().ok()?, parse_root(root.trim())?))
+}
+
+fn parse_root(s: &str) -> Option<H128> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let mut root = H128::zero();
+    hex::decode_to_slice(s, &mut root.0).ok()?;
+    Some(root)
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use alloc::string::String;
+
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub(super) struct Entry {
+        pub epoch: usize,
+        pub root: String,
+    }
+}
+
+/// Merkle tree over the roots of a contiguous range of epochs.
+///
+/// The leaf at index `i` is the root of epoch `start_epoch + i`, used as is.
+/// Leaves are paired with the node hash of `H`, the one of the dataset tree
+/// by default, and the tree is padded on the right with zero subtrees up to
+/// the next power of two.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootsAccumulator<H: MerkleHasher = Sha256Hasher> {
+    start_epoch: usize,
+    // `levels[0]` are the leaves, the last level holds the root only.
+    levels: Vec<Vec<Hash>>,
+    // `zero_hashes[d]` is the hash of a zero subtree of depth `d`.
+    zero_hashes: Vec<Hash>,
+    hasher: PhantomData<H>,
+}
+
+impl<H: MerkleHasher> RootsAccumulator<H> {
+    fn new(start_epoch: usize, leaves: Vec<Hash>) -> Self {
+        let depth = depth_for(leaves.len());
+        let zero_hashes = H::zero_hashes(depth);
+        let mut levels = vec![leaves];
+        for zero in &zero_hashes[..depth] {
+            let level = levels.last().expect("at least the leaves");
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => H::hash_nodes(left, right),
+                    [left] => H::hash_nodes(left, zero),
+                    _ => unreachable!("chunks of two"),
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self {
+            start_epoch,
+            levels,
+            zero_hashes,
+            hasher: PhantomData,
+        }
+    }
+
+    /// The single commitment to all the roots.
+    pub fn root(&self) -> Hash { self.levels[self.depth()][0] }
+
+    /// Length of every inclusion proof.
+    pub fn depth(&self) -> usize { self.levels.len() - 1 }
+
+    pub fn start_epoch(&self) -> usize { self.start_epoch }
+
+    /// Number of epochs covered.
+    pub fn len(&self) -> usize { self.levels[0].len() }
+
+    pub fn is_empty(&self) -> bool { self.levels[0].is_empty() }
+
+    /// Return the root of `epoch` and the (bottom-up) proof of its inclusion,
+    /// or `None` if the epoch is not covered by this accumulator.
+    pub fn generate_proof(&self, epoch: usize) -> Option<(H128, Vec<Hash>)> {
+        let index = epoch.checked_sub(self.start_epoch)?;
+        if index >= self.len() {
+            return None;
+        }
+        let proof = (0..self.depth())
+            .map(|d| {
+                let sibling = (index >> d) ^ 1;
+                self.levels[d]
+                    .get(sibling)
+                    .copied()
+                    .unwrap_or(self.zero_hashes[d])
+            })
+            .collect();
+        Some((H128(self.levels[0][index].0), proof))
+    }
+}
+
+/// Verify a proof that `epoch_root` is the leaf at `index` of the accumulator
+/// with the given `root`. The index is the epoch minus the accumulator's start
+/// epoch.
+pub fn verify_root_proof(
+    epoch_root: H128,
+    branch: &[Hash],
+    index: usize,
+    root: Hash,
+) -> bool {
+    verify_root_proof_with_hasher::<Sha256Hasher>(
+        epoch_root, branch, index, root,
+    )
+}
+
+/// Same as [`verify_root_proof`], for an accumulator hashed with `H`.
+pub fn verify_root_proof_with_hasher<H: MerkleHasher>(
+    epoch_root: H128,
+    branch: &[Hash],
+    index: usize,
+    root: Hash,
+) -> bool {
+    // shifting the index by the depth or more would overflow.
+    if branch.len() >= usize::BITS as usize || index >> branch.len() != 0 {
+        return false;
+    }
+    let leaf = Hash::from(epoch_root.0);
+    let depth = branch.len();
+    mtree::merkle_root_from_branch::<H>(leaf, branch, depth, index) == root
+}
+
+/// Smallest depth whose tree has room for `n` leaves.
+fn depth_for(n: usize) -> usize {
+    n.next_power_of_two().trailing_zeros() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(n: usize) -> EpochRoots {
+        let mut roots = EpochRoots::new();
+        for epoch in 0..n {
+            roots.insert(epoch + 10, H128::from_low_u64_be(epoch as u64 + 1));
+        }
+        roots
+    }
+
+    #[test]
+    fn lines_roundtrip() {
+        let roots = table(3);
+        let lines = roots.to_lines();
+        assert_eq!(
+            lines.lines().next().unwrap(),
+            "10:0x00000000000000000000000000000001"
+        );
+        assert_eq!(EpochRoots::parse(&lines).unwrap(), roots);
+        assert_eq!(
+            EpochRoots::parse("1:0x00\n"),
+            Err(RootsError::InvalidLine(1))
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_roundtrip() {
+        let roots = table(3);
+        assert_eq!(EpochRoots::from_json(&roots.to_json()).unwrap(), roots);
+    }
+
+    #[test]
+    fn accumulator_proofs() {
+        for n in 1..=9 {
+            let roots = table(n);
+            let acc = roots.accumulator().unwrap();
+            assert_eq!(acc.depth(), depth_for(n));
+            for (epoch, expected) in roots.iter() {
+                let (root, proof) = acc.generate_proof(epoch).unwrap();
+                assert_eq!(root, expected);
+                let index = epoch - acc.start_epoch();
+                assert!(verify_root_proof(root, &proof, index, acc.root()));
+                assert!(!verify_root_proof(
+                    H128::zero(),
+                    &proof,
+                    index,
+                    acc.root()
+                ));
+            }
+            assert!(acc.generate_proof(9).is_none());
+            assert!(acc.generate_proof(10 + n).is_none());
+        }
+
+        let acc = table(1).accumulator().unwrap();
+        let long = vec![Hash::default(); 64];
+        assert!(!verify_root_proof(H128::zero(), &long, 0, acc.root()));
+    }
+
+    #[test]
+    fn accumulator_hashers() {
+        use mtree::Keccak256Hasher;
+
+        let roots = table(3);
+        let sha = roots.accumulator().unwrap();
+        let keccak =
+            roots.accumulator_with_hasher::<Keccak256Hasher>().unwrap();
+        assert_ne!(keccak.root(), sha.root());
+        let (root, proof) = keccak.generate_proof(12).unwrap();
+        // the third leaf is paired with a Keccak-256 zero subtree.
+        assert_eq!(proof[0], <Keccak256Hasher>::zero_hashes(0)[0]);
+        let leaf = |epoch: u64| Hash::from(H128::from_low_u64_be(epoch).0);
+        let left = <Keccak256Hasher>::hash_nodes(&leaf(1), &leaf(2));
+        assert_eq!(proof[1], left);
+        assert!(verify_root_proof_with_hasher::<Keccak256Hasher>(
+            root,
+            &proof,
+            2,
+            keccak.root(),
+        ));
+        assert!(!verify_root_proof(root, &proof, 2, keccak.root()));
+    }
+
+    #[test]
+    fn accumulator_needs_contiguous_epochs() {
+        let mut roots = table(2);
+        roots.insert(13, H128::zero());
+        assert_eq!(roots.accumulator(), Err(RootsError::MissingEpoch(12)));
+        assert_eq!(EpochRoots::new().accumulator(), Err(RootsError::Empty));
+    }
+}
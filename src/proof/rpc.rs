@@ -0,0 +1,249 @@
+//! Block headers in the JSON-RPC block format.
+//!
+//! `eth_getBlockByNumber` and friends return blocks as JSON objects with
+//! camelCase fields. Quantities (`number`, `difficulty`, `gasLimit`, ...) are
+//! `0x`-prefixed hex without leading zeros, and data (`parentHash`, `nonce`,
+//! `extraData`, ...) is `0x`-prefixed hex of exactly the size of the field.
+//! Both rules are enforced when parsing, and the block `hash` is checked
+//! against the one of the decoded header.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use ethereum_types::{Bloom, H160, H256, H64, U256};
+use serde_json::{json, Map, Value};
+
+use super::types::BlockHeader;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RpcError {
+    // Malformed JSON document, or not an object
+    Json,
+    // Field is missing (name)
+    MissingField(&'static str),
+    // Field is not a valid quantity or data (name)
+    InvalidField(&'static str),
+    // `hash` of the block is not the one of the decoded header
+    HashMismatch,
+}
+
+impl BlockHeader {
+    /// Parse a JSON-RPC block object, see [`BlockHeader::from_rpc`].
+    pub fn from_rpc_json(s: &str) -> Result<Self, RpcError> {
+        let block: Value = serde_json::from_str(s).map_err(|_| RpcError::Json)?;
+        Self::from_rpc(&block)
+    }
+
+    /// The header of a JSON-RPC block object. Fields other than the header
+    /// ones are ignored; `hash` is checked unless it is `null`, as it is for
+    /// pending blocks, whose `null` `miner` and `nonce` decode as zero.
+    pub fn from_rpc(block: &Value) -> Result<Self, RpcError> {
+        let block = block.as_object().ok_or(RpcError::Json)?;
+        let pending = matches!(block.get("hash"), None | Some(Value::Null));
+        let header = Self {
+            parent_hash: H256(data(block, "parentHash")?),
+            uncles_hash: H256(data(block, "sha3Uncles")?),
+            author: H160(pending_data(block, "miner", pending)?),
+            state_root: H256(data(block, "stateRoot")?),
+            transactions_root: H256(data(block, "transactionsRoot")?),
+            receipts_root: H256(data(block, "receiptsRoot")?),
+            log_bloom: Bloom(data(block, "logsBloom")?),
+            difficulty: quantity(block, "difficulty")?,
+            number: quantity(block, "number")?,
+            gas_limit: quantity_u64(block, "gasLimit")?,
+            gas_used: quantity_u64(block, "gasUsed")?,
+            timestamp: quantity_u64(block, "timestamp")?,
+            extra_data: bytes(block, "extraData")?,
+            mix_hash: H256(data(block, "mixHash")?),
+            nonce: H64(pending_data(block, "nonce", pending)?),
+            base_fee_per_gas: match block.get("baseFeePerGas") {
+                None | Some(Value::Null) => None,
+                Some(_) => Some(quantity(block, "baseFeePerGas")?),
+            },
+        };
+        match block.get("hash") {
+            None | Some(Value::Null) => {},
+            Some(_) if H256(data(block, "hash")?) == header.hash() => {},
+            Some(_) => return Err(RpcError::HashMismatch),
+        }
+        Ok(header)
+    }
+
+    /// The JSON-RPC block object of the header, with its `hash`.
+    pub fn to_rpc(&self) -> Value {
+        let mut block = json!({
+            "hash": to_data(self.hash().as_bytes()),
+            "parentHash": to_data(self.parent_hash.as_bytes()),
+            "sha3Uncles": to_data(self.uncles_hash.as_bytes()),
+            "miner": to_data(self.author.as_bytes()),
+            "stateRoot": to_data(self.state_root.as_bytes()),
+            "transactionsRoot": to_data(self.transactions_root.as_bytes()),
+            "receiptsRoot": to_data(self.receipts_root.as_bytes()),
+            "logsBloom": to_data(self.log_bloom.as_bytes()),
+            "difficulty": to_quantity(self.difficulty),
+            "number": to_quantity(self.number),
+            "gasLimit": to_quantity(self.gas_limit.into()),
+            "gasUsed": to_quantity(self.gas_used.into()),
+            "timestamp": to_quantity(self.timestamp.into()),
+            "extraData": to_data(&self.extra_data),
+            "mixHash": to_data(self.mix_hash.as_bytes()),
+            "nonce": to_data(self.nonce.as_bytes()),
+        });
+        if let Some(base_fee) = self.base_fee_per_gas {
+            block["baseFeePerGas"] = to_quantity(base_fee).into();
+        }
+        block
+    }
+}
+
+fn field<'a>(
+    block: &'a Map<String, Value>,
+    name: &'static str,
+) -> Result<&'a str, RpcError> {
+    let value = block.get(name).ok_or(RpcError::MissingField(name))?;
+    value.as_str().ok_or(RpcError::InvalidField(name))
+}
+
+fn bytes(
+    block: &Map<String, Value>,
+    name: &'static str,
+) -> Result<Vec<u8>, RpcError> {
+    let hex = field(block, name)?
+        .strip_prefix("0x")
+        .ok_or(RpcError::InvalidField(name))?;
+    let mut bytes = vec![0u8; hex.len() / 2];
+    hex::decode_to_slice(hex, &mut bytes)
+        .map_err(|_| RpcError::InvalidField(name))?;
+    Ok(bytes)
+}
+
+fn data<const N: usize>(
+    block: &Map<String, Value>,
+    name: &'static str,
+) -> Result<[u8; N], RpcError> {
+    let bytes = bytes(block, name)?;
+    bytes.try_into().map_err(|_| RpcError::InvalidField(name))
+}
+
+// Same as `data`, with `null` decoding as zero in a pending block.
+fn pending_data<const N: usize>(
+    block: &Map<String, Value>,
+    name: &'static str,
+    pending: bool,
+) -> Result<[u8; N], RpcError> {
+    match block.get(name) {
+        Some(Value::Null) if pending => Ok([0u8; N]),
+        _ => data(block, name),
+    }
+}
+
+fn quantity(
+    block: &Map<String, Value>,
+    name: &'static str,
+) -> Result<U256, RpcError> {
+    let invalid = RpcError::InvalidField(name);
+    let hex = field(block, name)?.strip_prefix("0x").ok_or(invalid.clone())?;
+    let canonical = hex == "0" || !(hex.is_empty() || hex.starts_with('0'));
+    if !canonical || hex.len() > 64 {
+        return Err(invalid);
+    }
+    U256::from_str_radix(hex, 16).map_err(|_| invalid)
+}
+
+fn quantity_u64(
+    block: &Map<String, Value>,
+    name: &'static str,
+) -> Result<u64, RpcError> {
+    let value = quantity(block, name)?;
+    if value > u64::MAX.into() {
+        return Err(RpcError::InvalidField(name));
+    }
+    Ok(value.low_u64())
+}
+
+fn to_data(bytes: &[u8]) -> String { format!("0x{}", hex::encode(bytes)) }
+
+fn to_quantity(value: U256) -> String { format!("0x{:x}", value) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> BlockHeader {
+        let rlp = include_str!("../../tests/fixtures/2.rlp");
+        rlp::decode(&hex::decode(rlp.trim()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let header = fixture();
+        let block = header.to_rpc();
+        assert_eq!(
+            block["hash"],
+            "0xb495a1d7e6663152ae92708da4843337b958146015a2802f4193a410044698c9"
+        );
+        assert_eq!(block["number"], "0x2");
+        assert_eq!(block["gasUsed"], "0x0");
+        assert_eq!(block.get("baseFeePerGas"), None);
+        assert_eq!(BlockHeader::from_rpc(&block), Ok(header.clone()));
+
+        let mut london = header;
+        london.base_fee_per_gas = Some(1_000_000_000u64.into());
+        let block = london.to_rpc();
+        assert_eq!(block["baseFeePerGas"], "0x3b9aca00");
+        let json = block.to_string();
+        assert_eq!(BlockHeader::from_rpc_json(&json), Ok(london.clone()));
+        let rlp = rlp::encode(&london);
+        assert_eq!(rlp::decode::<BlockHeader>(&rlp), Ok(london));
+    }
+
+    #[test]
+    fn strict_fields() {
+        let block = fixture().to_rpc();
+        let with = |name: &str, value: Value| {
+            let mut block = block.clone();
+            block[name] = value;
+            BlockHeader::from_rpc(&block)
+        };
+        let invalid = |name| Err(RpcError::InvalidField(name));
+        assert_eq!(with("number", "0x02".into()), invalid("number"));
+        assert_eq!(with("number", "0x".into()), invalid("number"));
+        assert_eq!(with("number", "2".into()), invalid("number"));
+        assert_eq!(with("number", 2.into()), invalid("number"));
+        assert_eq!(with("gasUsed", "0x00".into()), invalid("gasUsed"));
+        assert_eq!(
+            with("gasLimit", "0x10000000000000000".into()),
+            invalid("gasLimit"),
+        );
+        assert_eq!(with("nonce", "0x00".into()), invalid("nonce"));
+        assert_eq!(with("extraData", "0x123".into()), invalid("extraData"));
+        assert_eq!(with("number", "0x3".into()), Err(RpcError::HashMismatch));
+        assert!(with("hash", Value::Null).is_ok());
+        assert!(with("baseFeePerGas", Value::Null).is_ok());
+        assert_eq!(with("nonce", Value::Null), invalid("nonce"));
+        assert_eq!(with("miner", Value::Null), invalid("miner"));
+
+        let mut block = block.clone();
+        block.as_object_mut().unwrap().remove("mixHash");
+        assert_eq!(
+            BlockHeader::from_rpc(&block),
+            Err(RpcError::MissingField("mixHash")),
+        );
+        assert_eq!(BlockHeader::from_rpc_json("[]"), Err(RpcError::Json));
+    }
+
+    #[test]
+    fn pending_block() {
+        let mut block = fixture().to_rpc();
+        block["hash"] = Value::Null;
+        block["miner"] = Value::Null;
+        block["nonce"] = Value::Null;
+        assert_eq!(
+            BlockHeader::from_rpc(&block),
+            Ok(BlockHeader {
+                author: H160::zero(),
+                nonce: H64::zero(),
+                ..fixture()
+            }),
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! Sealing of block headers.
+//!
+//! Sealing a header is mining it to its own difficulty: searching for a
+//! nonce whose final hash meets the boundary of the difficulty, see
+//! [`crate::search`]. The nonce and mix hash of the solution are then written
+//! back into the header, which is ready for RLP encoding and passes
+//! [`super::verify::verify_seal`].
+
+use core::ops::RangeInclusive;
+
+use ethereum_types::{H256, U256};
+
+use super::types::BlockHeader;
+use crate::{LightDAG, SearchResult};
+
+impl BlockHeader {
+    /// The header sealed by the first nonce of `nonces` that does, hashed
+    /// with the light cache of `dag`, the DAG of the epoch of the header.
+    /// `None` if no nonce of the range does, or if the difficulty is zero.
+    pub fn seal_light(
+        self,
+        dag: &LightDAG,
+        nonces: RangeInclusive<u64>,
+    ) -> Option<Self> {
+        self.seal_with(|hash, boundary| dag.search(hash, boundary, nonces))
+    }
+
+    /// [`BlockHeader::seal_light`] with the full dataset of the epoch.
+    pub fn seal_full(
+        self,
+        full_size: usize,
+        dataset: &[u8],
+        nonces: RangeInclusive<u64>,
+    ) -> Option<Self> {
+        self.seal_with(|hash, boundary| {
+            crate::search_full(hash, full_size, dataset, boundary, nonces)
+        })
+    }
+
+    fn seal_with<F>(mut self, search: F) -> Option<Self>
+    where
+        F: FnOnce(H256, U256) -> SearchResult,
+    {
+        // any hash would meet the boundary of a zero difficulty.
+        if self.difficulty.is_zero() {
+            return None;
+        }
+        let boundary = crate::cross_boundary(self.difficulty);
+        let solution = search(self.seal_hash(), boundary).solution?;
+        self.nonce = solution.nonce;
+        self.mix_hash = solution.mix_hash;
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::verify::verify_seal;
+
+    fn unsealed(difficulty: u64) -> BlockHeader {
+        BlockHeader {
+            difficulty: difficulty.into(),
+            number: 1.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn seal() {
+        let dag = LightDAG::tiny(0);
+        let header = unsealed(64).seal_light(&dag, 0..=u64::MAX).unwrap();
+        assert_eq!(verify_seal(&header, &dag), Ok(()));
+        let rlp = rlp::encode(&header);
+        assert_eq!(rlp::decode::<BlockHeader>(&rlp), Ok(header.clone()));
+
+        let mut dataset = vec![0u8; dag.full_size];
+        crate::make_dataset(&mut dataset, &dag.cache);
+        let nonces = 0..=u64::MAX;
+        let full = unsealed(64).seal_full(dag.full_size, &dataset, nonces);
+        assert_eq!(full, Some(header.clone()));
+
+        // the search starts at the given nonce.
+        let start = header.nonce.to_low_u64_be() + 1;
+        let next = unsealed(64).seal_light(&dag, start..=u64::MAX).unwrap();
+        assert!(next.nonce.to_low_u64_be() >= start);
+        assert_eq!(verify_seal(&next, &dag), Ok(()));
+
+        assert_eq!(unsealed(0).seal_light(&dag, 0..=u64::MAX), None);
+        assert_eq!(unsealed(u64::MAX).seal_light(&dag, 0..=3), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! Hex-string serde helpers for byte-like proof types.
+//!
+//! Values are written as `0x`-prefixed lowercase hex, the layout of the proof
+//! JSON files: byte strings in full, fixed-size values without their leading
+//! zeros. When reading, the prefix is optional, and fixed-size values may
+//! come with any number of leading zeros, down to an odd number of digits.
+//!
+//! Use with `#[serde(with = "ethash::serde_hex")]`, or the [`seq`] variant.
+
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt;
+
+use ethereum_types::{H128, H256, H64};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::{SerializeSeq, Serializer};
+
+use super::mtree::{Hash, Word};
+
+/// Types that are serialized as a hex string of their bytes.
+pub trait HexBytes: Sized {
+    /// Whether the value has a fixed size, and so is written without its
+    /// leading zeros.
+    const FIXED_SIZE: bool;
+
+    fn hex_bytes(&self) -> &[u8];
+
+    /// Build the value from decoded bytes, `None` if they do not fit.
+    fn from_hex_bytes(bytes: &[u8]) -> Option<Self>;
+}
+
+impl HexBytes for Vec<u8> {
+    const FIXED_SIZE: bool = false;
+
+    fn hex_bytes(&self) -> &[u8] { self }
+
+    fn from_hex_bytes(bytes: &[u8]) -> Option<Self> { Some(bytes.to_vec()) }
+}
+
+macro_rules! impl_fixed_hex_bytes {
+    ($($t:ty => $len:expr),*) => {$(
+        impl HexBytes for $t {
+            const FIXED_SIZE: bool = true;
+
+            fn hex_bytes(&self) -> &[u8] { &self.0 }
+
+            fn from_hex_bytes(bytes: &[u8]) -> Option<Self> {
+                let mut inner = [0u8; $len];
+                let pad = ($len as usize).checked_sub(bytes.len())?;
+                inner[pad..].copy_from_slice(bytes);
+                Some(Self(inner))
+            }
+        }
+    )*};
+}
+
+impl_fixed_hex_bytes!(
+    H64 => 8, H128 => 16, H256 => 32, Hash => 16, Word => 128
+);
+
+fn encode<T: HexBytes>(value: &T) -> String {
+    let hex = hex::encode(value.hex_bytes());
+    let digits = match T::FIXED_SIZE {
+        true => match hex.trim_start_matches('0') {
+            "" => "0",
+            digits => digits,
+        },
+        false => &hex,
+    };
+    alloc::format!("0x{}", digits)
+}
+
+fn decode<T: HexBytes>(s: &str) -> Option<T> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    // Fixed-size values may come with their leading zero stripped.
+    let padded;
+    let s = if s.len() % 2 == 1 {
+        if !T::FIXED_SIZE {
+            return None;
+        }
+        padded = alloc::format!("0{}", s);
+        padded.as_str()
+    } else {
+        s
+    };
+    let mut bytes = vec![0u8; s.len() / 2];
+    hex::decode_to_slice(s, &mut bytes).ok()?;
+    T::from_hex_bytes(&bytes)
+}
+
+struct HexVisitor<T>(core::marker::PhantomData<T>);
+
+impl<'de, T: HexBytes> Visitor<'de> for HexVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a hex string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        decode(v)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+pub fn serialize<T: HexBytes, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode(value))
+}
+
+pub fn deserialize<'de, T: HexBytes, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    deserializer.deserialize_str(HexVisitor(core::marker::PhantomData))
+}
+
+/// Sequences of hex strings, e.g. Merkle branches.
+pub mod seq {
+    use super::*;
+
+    pub fn serialize<T: HexBytes, S: Serializer>(
+        values: &[T],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(values.len()))?;
+        for value in values {
+            seq.serialize_element(&encode(value))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, T: HexBytes, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<T>, D::Error> {
+        struct SeqVisitor<T>(core::marker::PhantomData<T>);
+
+        impl<'de, T: HexBytes> Visitor<'de> for SeqVisitor<T> {
+            type Value = Vec<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a list of hex strings")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Vec<T>, A::Error> {
+                let mut values =
+                    Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(s) = seq.next_element::<String>()? {
+                    let value = decode(&s).ok_or_else(|| {
+                        de::Error::invalid_value(de::Unexpected::Str(&s), &self)
+                    })?;
+                    values.push(value);
+                }
+                Ok(values)
+            }
+        }
+
+        deserializer.deserialize_seq(SeqVisitor(core::marker::PhantomData))
+    }
+}
+
+macro_rules! impl_serde_hex {
+    ($($t:ty),*) => {$(
+        impl serde::Serialize for $t {
+            fn serialize<S: Serializer>(
+                &self,
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                serialize(self, serializer)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                deserialize(deserializer)
+            }
+        }
+    )*};
+}
+
+impl_serde_hex!(Hash, Word);
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::super::types::{BlockHeader, BlockWithProofs};
+    use super::*;
+
+    // The layout the proof files were written with before these types could
+    // be serialized.
+    #[derive(serde::Serialize)]
+    struct HandEncoded {
+        header_rlp: String,
+        merkle_root: String,
+        elements: Vec<String>,
+        merkle_proofs: Vec<String>,
+        proof_length: u64,
+    }
+
+    #[test]
+    fn block_with_proofs_layout() {
+        let block = BlockWithProofs {
+            header_rlp: vec![0x00, 0xf9, 0x02],
+            merkle_root: Hash([0xab; 16]),
+            elements: vec![H256::from_low_u64_be(0x102), H256::zero()],
+            merkle_proofs: vec![Hash([3; 16]), Hash::zero()],
+            proof_length: 2,
+        };
+        let hand = HandEncoded {
+            header_rlp: "0x00f902".into(),
+            merkle_root: alloc::format!("0x{}", "ab".repeat(16)),
+            elements: vec!["0x102".into(), "0x0".into()],
+            merkle_proofs: vec![
+                alloc::format!("0x3{}", "03".repeat(15)),
+                "0x0".into(),
+            ],
+            proof_length: 2,
+        };
+        let json = serde_json::to_string_pretty(&block).unwrap();
+        assert_eq!(json, serde_json::to_string_pretty(&hand).unwrap());
+        let decoded: BlockWithProofs = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, block);
+    }
+
+    #[test]
+    fn odd_lengths() {
+        let word = |s: &str| decode::<H256>(s);
+        assert_eq!(word("0x102"), Some(H256::from_low_u64_be(0x102)));
+        assert_eq!(word("0102"), Some(H256::from_low_u64_be(0x102)));
+        assert_eq!(word(&"1".repeat(65)), None);
+        assert_eq!(decode::<Vec<u8>>("0x0102"), Some(vec![1, 2]));
+        assert_eq!(decode::<Vec<u8>>("0x102"), None);
+    }
+
+    #[test]
+    fn fixture() {
+        let json = include_str!("../../tests/fixtures/2.json");
+        let block: BlockWithProofs = serde_json::from_str(json).unwrap();
+        assert_eq!(block.proof_length, 23);
+        assert_eq!(block.elements.len(), 64 * 4);
+        assert_eq!(block.merkle_proofs.len(), 64 * 23);
+
+        // written back byte for byte, with the indentation of the file.
+        let mut written = Vec::new();
+        let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+        let mut serializer =
+            serde_json::Serializer::with_formatter(&mut written, formatter);
+        serde::Serialize::serialize(&block, &mut serializer).unwrap();
+        written.push(b'\n');
+        assert_eq!(String::from_utf8(written).unwrap(), json);
+
+        let header: BlockHeader = rlp::decode(&block.header_rlp).unwrap();
+        let json = serde_json::to_string(&header).unwrap();
+        assert_eq!(serde_json::from_str::<BlockHeader>(&json).unwrap(), header);
+    }
+}
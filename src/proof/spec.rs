@@ -0,0 +1,237 @@
+//! Chain specifications.
+//!
+//! A [`ChainSpec`] gathers what verifying the headers of a real chain takes:
+//! its genesis hash and the blocks of its forks (difficulty adjustments and
+//! bombs, London, ECIP-1099, ECBP-1100, the Merge). Presets cover the public
+//! Ethash chains, not the proof of authority ones such as Goerli; custom
+//! specs are parsed from JSON, with [`ChainConfig`] and [`DifficultyConfig`]
+//! fields defaulting to the ones of [`ChainConfig::default`].
+
+use alloc::{string::String, vec};
+
+use ethereum_types::{H256, U256};
+use hex_literal::hex;
+
+use super::chain::ChainConfig;
+use super::difficulty::DifficultyConfig;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChainSpec {
+    pub name: String,
+    pub chain_id: u64,
+    pub genesis_hash: H256,
+    pub config: ChainConfig,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SpecError {
+    // Malformed JSON spec, or missing fields
+    Json,
+    // A divisor of the spec is zero (name)
+    ZeroDivisor(&'static str),
+    // `bomb_delays` are not sorted by block
+    UnsortedBombDelays,
+}
+
+impl ChainSpec {
+    /// Ethereum mainnet, up to the Merge.
+    pub fn mainnet() -> Self {
+        Self {
+            name: "mainnet".into(),
+            chain_id: 1,
+            genesis_hash: H256(hex!(
+                "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+            )),
+            config: ChainConfig {
+                difficulty: DifficultyConfig {
+                    homestead_block: 1_150_000,
+                    byzantium_block: Some(4_370_000),
+                    bomb_delays: vec![
+                        (4_370_000, 3_000_000),
+                        (7_280_000, 5_000_000),
+                        (9_200_000, 9_000_000),
+                        (12_965_000, 9_700_000),
+                        (13_773_000, 10_700_000),
+                        (15_050_000, 11_400_000),
+                    ],
+                    ..Default::default()
+                },
+                london_block: Some(12_965_000),
+                terminal_total_difficulty: Some(
+                    U256::from_dec_str("58750000000000000000000").unwrap(),
+                ),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Ethereum Classic, which kept the genesis of mainnet.
+    pub fn classic() -> Self {
+        Self {
+            name: "classic".into(),
+            chain_id: 61,
+            genesis_hash: Self::mainnet().genesis_hash,
+            config: ChainConfig {
+                difficulty: DifficultyConfig {
+                    homestead_block: 1_150_000,
+                    byzantium_block: Some(8_772_000),
+                    bomb_pause: Some(3_000_000..5_000_000),
+                    bomb_defuse_block: Some(5_900_000),
+                    ..Default::default()
+                },
+                ecbp1100: Some(11_380_000..19_250_000),
+                ecip1099_block: Some(11_700_000),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Mordor, the Ethereum Classic testnet.
+    pub fn mordor() -> Self {
+        Self {
+            name: "mordor".into(),
+            chain_id: 63,
+            genesis_hash: H256(hex!(
+                "a68ebde7932eccb177d38d55dcc6461a019dd795a681e59b5a3e4f3a7259a3f1"
+            )),
+            config: ChainConfig {
+                difficulty: DifficultyConfig {
+                    byzantium_block: Some(0),
+                    bomb_defuse_block: Some(0),
+                    ..Default::default()
+                },
+                ecbp1100: Some(2_380_000..9_957_000),
+                ecip1099_block: Some(2_520_000),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Ropsten, up to the Merge.
+    pub fn ropsten() -> Self {
+        Self {
+            name: "ropsten".into(),
+            chain_id: 3,
+            genesis_hash: H256(hex!(
+                "41941023680923e0fe4d74a34bdac8141f2540e3ae90623718e47d66d1ca4a2d"
+            )),
+            config: ChainConfig {
+                difficulty: DifficultyConfig {
+                    byzantium_block: Some(1_700_000),
+                    bomb_delays: vec![
+                        (1_700_000, 3_000_000),
+                        (4_230_000, 5_000_000),
+                        (7_117_117, 9_000_000),
+                        (10_499_401, 9_700_000),
+                    ],
+                    ..Default::default()
+                },
+                london_block: Some(10_499_401),
+                terminal_total_difficulty: Some(
+                    50_000_000_000_000_000u64.into(),
+                ),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The preset named `name`, or with `name` as chain id.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "mainnet" | "1" => Some(Self::mainnet()),
+            "classic" | "61" => Some(Self::classic()),
+            "mordor" | "63" => Some(Self::mordor()),
+            "ropsten" | "3" => Some(Self::ropsten()),
+            _ => None,
+        }
+    }
+
+    /// Check the parameters of the spec that would make validation panic or
+    /// misbehave.
+    pub fn check(&self) -> Result<(), SpecError> {
+        let difficulty = &self.config.difficulty;
+        if difficulty.bound_divisor.is_zero() {
+            return Err(SpecError::ZeroDivisor("bound_divisor"));
+        }
+        if self.config.gas_limit_bound_divisor == 0 {
+            return Err(SpecError::ZeroDivisor("gas_limit_bound_divisor"));
+        }
+        if !difficulty.bomb_delays.windows(2).all(|w| w[0].0 <= w[1].0) {
+            return Err(SpecError::UnsortedBombDelays);
+        }
+        Ok(())
+    }
+
+    /// Parse and [check](ChainSpec::check) a JSON spec, in the format of its
+    /// serde serialization.
+    #[cfg(feature = "json")]
+    pub fn from_json(s: &str) -> Result<Self, SpecError> {
+        let spec: Self = serde_json::from_str(s).map_err(|_| SpecError::Json)?;
+        spec.check()?;
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        let mainnet = ChainSpec::mainnet();
+        let config = &mainnet.config;
+        assert_eq!(config.difficulty.bomb(12_964_999), U256::one() << 37);
+        assert_eq!(config.difficulty.bomb(12_965_000), U256::one() << 30);
+        assert!(config.is_london(12_965_000.into()));
+        assert_eq!(config.epoch(12_965_000), 432);
+
+        let classic = ChainSpec::preset("61").unwrap();
+        assert_eq!(classic.genesis_hash, mainnet.genesis_hash);
+        assert_eq!(classic.config.difficulty.bomb(20_000_000), U256::zero());
+        assert_eq!(classic.config.epoch_length(11_699_999), 30_000);
+        assert_eq!(classic.config.epoch(11_699_999), 389);
+        assert_eq!(classic.config.epoch(11_700_000), 195);
+        assert_eq!(ChainSpec::preset("mordor").unwrap().chain_id, 63);
+        assert_eq!(ChainSpec::preset("kovan"), None);
+        assert_eq!(ChainSpec::preset("goerli"), None);
+
+        for name in ["mainnet", "classic", "mordor", "ropsten"] {
+            let spec = ChainSpec::preset(name).unwrap();
+            assert_eq!(spec.name, name);
+            assert_eq!(spec.check(), Ok(()));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn custom_json() {
+        let json = r#"{
+            "name": "devnet",
+            "chain_id": 1337,
+            "genesis_hash": "0x0000000000000000000000000000000000000000000000000000000000000001",
+            "config": {
+                "difficulty": {"minimum": "0x1", "byzantium_block": 0},
+                "london_block": 0
+            }
+        }"#;
+        let spec = ChainSpec::from_json(json).unwrap();
+        assert_eq!(spec.genesis_hash, H256::from_low_u64_be(1));
+        assert_eq!(spec.config.difficulty.minimum, U256::one());
+        assert_eq!(spec.config.difficulty.bound_divisor, 2048.into());
+        assert_eq!(spec.config.gas_limit_bound_divisor, 1024);
+        assert_eq!(spec.config.terminal_total_difficulty, None);
+
+        let json = serde_json::to_string(&ChainSpec::classic()).unwrap();
+        assert_eq!(ChainSpec::from_json(&json), Ok(ChainSpec::classic()));
+        assert_eq!(ChainSpec::from_json("{}"), Err(SpecError::Json));
+        let zero = json.replace(
+            r#""gas_limit_bound_divisor":1024"#,
+            r#""gas_limit_bound_divisor":0"#,
+        );
+        assert_eq!(
+            ChainSpec::from_json(&zero),
+            Err(SpecError::ZeroDivisor("gas_limit_bound_divisor")),
+        );
+    }
+}
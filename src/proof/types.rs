@@ -2,7 +2,10 @@ use alloc::vec::Vec;
 use ethereum_types::{Address, Bloom, H256, H64, U256};
 use rlp::{Rlp, RlpStream};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+use super::mtree::{Hash, MerkleHasher, MerkleTree};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockHeader {
     pub parent_hash: H256,
     pub uncles_hash: H256,
@@ -16,9 +19,16 @@ pub struct BlockHeader {
     pub gas_limit: u64,
     pub gas_used: u64,
     pub timestamp: u64,
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_hex"))]
     pub extra_data: Vec<u8>,
     pub mix_hash: H256,
     pub nonce: H64,
+    /// Base fee of the block, from London (EIP-1559) on.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub base_fee_per_gas: Option<U256>,
 }
 
 impl BlockHeader {
@@ -37,7 +47,10 @@ impl BlockHeader {
     }
 
     fn stream_rlp(&self, stream: &mut RlpStream, partial: bool) {
-        stream.begin_list(13 + if !partial { 2 } else { 0 });
+        let fields = 13
+            + if !partial { 2 } else { 0 }
+            + self.base_fee_per_gas.is_some() as usize;
+        stream.begin_list(fields);
         stream.append(&self.parent_hash);
         stream.append(&self.uncles_hash);
         stream.append(&self.author);
@@ -56,6 +69,9 @@ impl BlockHeader {
             stream.append(&self.mix_hash);
             stream.append(&self.nonce);
         }
+        if let Some(base_fee) = &self.base_fee_per_gas {
+            stream.append(base_fee);
+        }
     }
 }
 
@@ -81,6 +97,55 @@ impl rlp::Decodable for BlockHeader {
             extra_data: rlp.val_at(12)?,
             mix_hash: rlp.val_at(13)?,
             nonce: rlp.val_at(14)?,
+            base_fee_per_gas: match rlp.item_count()? {
+                15 => None,
+                16 => Some(rlp.val_at(15)?),
+                _ => return Err(rlp::DecoderError::RlpIncorrectListLen),
+            },
         })
     }
 }
+
+/// A block header together with the dataset elements its seal accesses and
+/// their Merkle proofs, as handed to a verifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockWithProofs {
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_hex"))]
+    pub header_rlp: Vec<u8>,
+    pub merkle_root: Hash,
+    /// Four words per accessed element, see [`super::mtree::Word::to_words`].
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_hex::seq"))]
+    pub elements: Vec<H256>,
+    /// `proof_length` hashes per accessed element, bottom-up.
+    #[cfg_attr(feature = "serde", serde(with = "super::serde_hex::seq"))]
+    pub merkle_proofs: Vec<Hash>,
+    /// Depth of the dataset tree, i.e. the length of each proof.
+    pub proof_length: u64,
+}
+
+impl BlockWithProofs {
+    /// Collect the elements at `indices` (see [`crate::get_indices`]) and
+    /// their proofs from the dataset tree.
+    pub fn from_tree<H: MerkleHasher>(
+        header_rlp: Vec<u8>,
+        tree: &MerkleTree<H>,
+        indices: &[u32],
+    ) -> Self {
+        let depth = tree.depth();
+        let mut elements = Vec::with_capacity(indices.len() * 4);
+        let mut merkle_proofs = Vec::with_capacity(indices.len() * depth);
+        for index in indices {
+            let (word, _, proof) = tree.proof(*index as usize);
+            elements.extend(word.to_words::<H::Encoding>());
+            merkle_proofs.extend(proof);
+        }
+        Self {
+            proof_length: depth as u64,
+            header_rlp,
+            merkle_root: tree.hash(),
+            elements,
+            merkle_proofs,
+        }
+    }
+}
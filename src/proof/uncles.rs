@@ -0,0 +1,256 @@
+//! Validation of the uncles (ommers) included by a block.
+//!
+//! A block includes at most [`MAX_UNCLES`] uncles, committed to by its
+//! `uncles_hash`. Every uncle must be the child of one of the last
+//! [`MAX_UNCLE_DEPTH`] ancestors of the block other than its parent, must
+//! not be an ancestor itself, and must not have been included before, by the
+//! block or by these ancestors.
+
+use alloc::{collections::BTreeSet, vec::Vec};
+
+use ethereum_types::H256;
+use rlp::RlpStream;
+
+use super::types::BlockHeader;
+#[cfg(feature = "std")]
+use super::verify::{SealError, SealVerifier};
+
+pub const MAX_UNCLES: usize = 2;
+/// Number of generations an uncle may be behind the including block.
+pub const MAX_UNCLE_DEPTH: usize = 7;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum UncleError {
+    // The block includes more than `MAX_UNCLES` uncles
+    TooManyUncles(usize),
+    // `uncles_hash` of the block does not commit to its uncles
+    UnclesHashMismatch,
+    // Ancestors do not form the chain of the block, starting at its parent
+    BrokenAncestry,
+    // Uncle (by index) was already included
+    DuplicateUncle(usize),
+    // Uncle (by index) is an ancestor of the block
+    UncleIsAncestor(usize),
+    // Uncle (by index) is not the child of a recent ancestor other than the
+    // parent
+    DanglingUncle(usize),
+    // Number of the uncle (by index) does not follow its parent's
+    InvalidNumber(usize),
+    // Seal of the uncle (by index) is invalid
+    #[cfg(feature = "std")]
+    InvalidSeal(usize, SealError),
+}
+
+/// An ancestor of a block, with the uncles it included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ancestor {
+    pub header: BlockHeader,
+    pub uncles: Vec<BlockHeader>,
+}
+
+/// The `uncles_hash` of a block including `uncles`: the Keccak-256 of their
+/// RLP list.
+pub fn uncles_hash(uncles: &[BlockHeader]) -> H256 {
+    let mut stream = RlpStream::new_list(uncles.len());
+    for uncle in uncles {
+        stream.append(uncle);
+    }
+    crate::keccak_256(&stream.out()).into()
+}
+
+/// Check the uncles of `block` against its `ancestors`, parent first, but
+/// not their seals. Only the first [`MAX_UNCLE_DEPTH`] ancestors are used.
+pub fn check_uncles(
+    block: &BlockHeader,
+    uncles: &[BlockHeader],
+    ancestors: &[Ancestor],
+) -> Result<(), UncleError> {
+    if uncles.len() > MAX_UNCLES {
+        return Err(UncleError::TooManyUncles(uncles.len()));
+    }
+    if uncles_hash(uncles) != block.uncles_hash {
+        return Err(UncleError::UnclesHashMismatch);
+    }
+    if uncles.is_empty() {
+        return Ok(());
+    }
+
+    let ancestors = &ancestors[..ancestors.len().min(MAX_UNCLE_DEPTH)];
+    let mut hashes = Vec::with_capacity(ancestors.len());
+    let mut parent_hash = block.parent_hash;
+    for ancestor in ancestors {
+        let hash = ancestor.header.hash();
+        if hash != parent_hash {
+            return Err(UncleError::BrokenAncestry);
+        }
+        hashes.push(hash);
+        parent_hash = ancestor.header.parent_hash;
+    }
+    let mut included: BTreeSet<H256> = ancestors
+        .iter()
+        .flat_map(|ancestor| ancestor.uncles.iter().map(BlockHeader::hash))
+        .collect();
+    included.insert(block.hash());
+
+    for (i, uncle) in uncles.iter().enumerate() {
+        let hash = uncle.hash();
+        if !included.insert(hash) {
+            return Err(UncleError::DuplicateUncle(i));
+        }
+        if hashes.contains(&hash) {
+            return Err(UncleError::UncleIsAncestor(i));
+        }
+        // the parent of the block has a sibling of the block as child.
+        let parent = hashes
+            .iter()
+            .skip(1)
+            .position(|hash| *hash == uncle.parent_hash)
+            .map(|at| &ancestors[at + 1].header)
+            .ok_or(UncleError::DanglingUncle(i))?;
+        if uncle.number != parent.number + 1 {
+            return Err(UncleError::InvalidNumber(i));
+        }
+    }
+    Ok(())
+}
+
+/// [`check_uncles`], then verify the seals of the uncles with `verifier`.
+#[cfg(feature = "std")]
+pub fn verify_uncles(
+    block: &BlockHeader,
+    uncles: &[BlockHeader],
+    ancestors: &[Ancestor],
+    verifier: &mut SealVerifier,
+) -> Result<(), UncleError> {
+    check_uncles(block, uncles, ancestors)?;
+    match verifier.verify(uncles).first_failure() {
+        Some((i, error)) => Err(UncleError::InvalidSeal(i, error.clone())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::LightDAG;
+    use ethereum_types::U256;
+
+    /// A header of difficulty 1, so that any nonce seals it.
+    fn header(
+        parent_hash: H256,
+        number: U256,
+        extra: u8,
+        dag: &LightDAG,
+    ) -> BlockHeader {
+        let mut header = BlockHeader {
+            parent_hash,
+            uncles_hash: uncles_hash(&[]),
+            difficulty: U256::one(),
+            number,
+            extra_data: vec![extra],
+            ..Default::default()
+        };
+        header.mix_hash = dag.hashimoto(header.seal_hash(), header.nonce).0;
+        header
+    }
+
+    fn child(parent: &BlockHeader, extra: u8, dag: &LightDAG) -> BlockHeader {
+        header(parent.hash(), parent.number + 1, extra, dag)
+    }
+
+    #[test]
+    fn empty_uncles_hash() {
+        assert_eq!(
+            uncles_hash(&[]),
+            H256(hex_literal::hex!(
+                "1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347"
+            )),
+        );
+    }
+
+    #[test]
+    fn uncle_rules() {
+        let dag = LightDAG::tiny(0);
+        // chain: genesis <- a1 <- a2 <- a3, with b2 a sibling of a2 included
+        // by a3 and c2, c3 two more siblings.
+        let genesis = header(H256::zero(), U256::zero(), 0, &dag);
+        let a1 = child(&genesis, 0, &dag);
+        let a2 = child(&a1, 0, &dag);
+        let b2 = child(&a1, 1, &dag);
+        let c2 = child(&a1, 2, &dag);
+        let c3 = child(&a2, 2, &dag);
+        let mut a3 = child(&a2, 0, &dag);
+        a3.uncles_hash = uncles_hash(core::slice::from_ref(&b2));
+        let ancestors = [
+            Ancestor {
+                header: a3.clone(),
+                uncles: vec![b2.clone()],
+            },
+            Ancestor {
+                header: a2.clone(),
+                uncles: vec![],
+            },
+            Ancestor {
+                header: a1.clone(),
+                uncles: vec![],
+            },
+            Ancestor {
+                header: genesis.clone(),
+                uncles: vec![],
+            },
+        ];
+        let block = |uncles: &[BlockHeader]| {
+            let mut block = child(&a3, 0, &dag);
+            block.uncles_hash = uncles_hash(uncles);
+            block
+        };
+        let check = |uncles: &[BlockHeader]| {
+            check_uncles(&block(uncles), uncles, &ancestors)
+        };
+
+        assert_eq!(check(&[]), Ok(()));
+        assert_eq!(check(&[c2.clone(), c3.clone()]), Ok(()));
+        let mut verifier = SealVerifier::new(1, 1);
+        verifier.insert_dag(LightDAG::tiny(0));
+        let uncles = [c2.clone()];
+        assert_eq!(
+            verify_uncles(&block(&uncles), &uncles, &ancestors, &mut verifier),
+            Ok(()),
+        );
+
+        let three = [c2.clone(), c3.clone(), child(&a1, 3, &dag)];
+        assert_eq!(check(&three), Err(UncleError::TooManyUncles(3)));
+        let uncles = [c2.clone()];
+        assert_eq!(
+            check_uncles(&block(&[]), &uncles, &ancestors),
+            Err(UncleError::UnclesHashMismatch),
+        );
+        assert_eq!(
+            check(&[c2.clone(), c2.clone()]),
+            Err(UncleError::DuplicateUncle(1)),
+        );
+        assert_eq!(check(&[b2]), Err(UncleError::DuplicateUncle(0)));
+        assert_eq!(check(&[a2]), Err(UncleError::UncleIsAncestor(0)));
+        // a sibling of the block.
+        let sibling = child(&a3, 1, &dag);
+        assert_eq!(check(&[sibling]), Err(UncleError::DanglingUncle(0)));
+        let mut skipped = c2.clone();
+        skipped.number = U256::from(5);
+        assert_eq!(check(&[skipped]), Err(UncleError::InvalidNumber(0)));
+
+        let mut broken = ancestors.clone();
+        broken.swap(1, 2);
+        assert_eq!(
+            check_uncles(&block(&uncles), &uncles, &broken),
+            Err(UncleError::BrokenAncestry),
+        );
+
+        let mut forged = c2;
+        forged.mix_hash = H256::zero();
+        let uncles = [forged];
+        assert_eq!(
+            verify_uncles(&block(&uncles), &uncles, &ancestors, &mut verifier),
+            Err(UncleError::InvalidSeal(0, SealError::InvalidMixHash)),
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Seal verification of block headers.
+//!
+//! [`verify_seal`] checks a single header against the light cache of its
+//! epoch. [`quick_verify_seal`] only checks the difficulty of the claimed
+//! mix digest, without any cache, to cheaply turn away most forged headers
+//! before they are fully verified.
+//!
+//! Under std, [`SealVerifier`] checks whole ranges of headers: the headers
+//! are grouped by epoch, the cache of every epoch is built once and kept for
+//! the next ranges, and the seals are hashed in parallel.
+
+#[cfg(feature = "std")]
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+
+use ethereum_types::{BigEndianHash, H256};
+
+use super::types::BlockHeader;
+use crate::LightDAG;
+#[cfg(feature = "std")]
+use crate::{ECIP1099_EPOCH_LENGTH, EPOCH_LENGTH};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SealError {
+    // Mix hash of the header does not match the one of its nonce
+    InvalidMixHash,
+    // Final hash is above the boundary of the header difficulty
+    InvalidNonce,
+    // Difficulty is zero, as for proof of stake headers, which any hash
+    // would meet
+    ZeroDifficulty,
+    // Header is past the epochs the verifier builds caches for
+    UnsupportedEpoch(usize),
+}
+
+/// Check that the final hash of `header`, recomputed from its claimed mix
+/// digest with [`crate::quick_hashimoto`], meets its difficulty.
+pub fn quick_verify_seal(header: &BlockHeader) -> Result<(), SealError> {
+    let seal_hash = header.seal_hash();
+    quick_verify(header, seal_hash)
+}
+
+fn quick_verify(
+    header: &BlockHeader,
+    seal_hash: H256,
+) -> Result<(), SealError> {
+    if header.difficulty.is_zero() {
+        return Err(SealError::ZeroDifficulty);
+    }
+    let hash = crate::quick_hashimoto(seal_hash, header.nonce, header.mix_hash);
+    if hash.into_uint() > crate::cross_boundary(header.difficulty) {
+        return Err(SealError::InvalidNonce);
+    }
+    Ok(())
+}
+
+/// Verify the seal of `header` with the light cache of its epoch.
+pub fn verify_seal(
+    header: &BlockHeader,
+    dag: &LightDAG,
+) -> Result<(), SealError> {
+    let seal_hash = header.seal_hash();
+    quick_verify(header, seal_hash)?;
+    let mix_hash = dag.hashimoto(seal_hash, header.nonce).0;
+    if mix_hash != header.mix_hash {
+        return Err(SealError::InvalidMixHash);
+    }
+    Ok(())
+}
+
+/// Verifies the seals of batches of headers on the rayon pool.
+#[cfg(feature = "std")]
+pub struct SealVerifier {
+    // By seed epoch, see `LightDAG::seed_epoch`.
+    dags: BTreeMap<usize, Arc<LightDAG>>,
+    max_epochs: usize,
+    max_dags: usize,
+    ecip1099_block: Option<u64>,
+}
+
+#[cfg(feature = "std")]
+impl Default for SealVerifier {
+    fn default() -> Self { Self::new(2048, 3) }
+}
+
+#[cfg(feature = "std")]
+impl SealVerifier {
+    /// A verifier of the headers of the first `max_epochs` epochs, keeping
+    /// the caches of at most `max_dags` epochs between batches.
+    pub fn new(max_epochs: usize, max_dags: usize) -> Self {
+        Self {
+            dags: BTreeMap::new(),
+            max_epochs,
+            max_dags,
+            ecip1099_block: None,
+        }
+    }
+
+    /// Use epochs of [`ECIP1099_EPOCH_LENGTH`] blocks from `block` on.
+    pub fn set_ecip1099_block(&mut self, block: Option<u64>) {
+        self.ecip1099_block = block;
+    }
+
+    /// Use `dag` for the headers of its epoch.
+    pub fn insert_dag(&mut self, dag: LightDAG) {
+        self.dags.insert(dag.seed_epoch(), Arc::new(dag));
+    }
+
+    /// Epoch of `header` and the length of its epochs, or `None` if the
+    /// epoch does not fit in `usize`.
+    fn header_epoch(&self, header: &BlockHeader) -> Option<(usize, usize)> {
+        let length = match self.ecip1099_block {
+            Some(block) if header.number >= block.into() => {
+                ECIP1099_EPOCH_LENGTH
+            },
+            _ => EPOCH_LENGTH,
+        };
+        let epoch = header.number / length;
+        (epoch <= usize::MAX.into()).then(|| (epoch.as_usize(), length))
+    }
+
+    /// Verify the seals of `headers`, building the caches of the epochs they
+    /// belong to first. Only headers passing [`quick_verify_seal`] have their
+    /// caches built, and at most `max_dags` caches are built per call: the
+    /// headers of the later epochs missing one are reported as
+    /// [`SealError::UnsupportedEpoch`].
+    pub fn verify(&mut self, headers: &[BlockHeader]) -> SealReport {
+        use rayon::prelude::*;
+
+        // epoch, length of the epochs and seal hash of the headers passing
+        // the quick check.
+        let checked: Vec<Result<(usize, usize, H256), SealError>> = headers
+            .par_iter()
+            .map(|header| {
+                let (epoch, length) = match self.header_epoch(header) {
+                    Some(epoch) if epoch.0 < self.max_epochs => epoch,
+                    epoch => {
+                        let epoch = epoch.map_or(usize::MAX, |e| e.0);
+                        return Err(SealError::UnsupportedEpoch(epoch));
+                    },
+                };
+                let seal_hash = header.seal_hash();
+                quick_verify(header, seal_hash)?;
+                Ok((epoch, length, seal_hash))
+            })
+            .collect();
+        let seed_epoch = |epoch: usize, length| epoch * length / EPOCH_LENGTH;
+        let mut missing: Vec<(usize, usize)> = checked
+            .iter()
+            .flatten()
+            .map(|(epoch, length, _)| (*epoch, *length))
+            .filter(|(e, l)| !self.dags.contains_key(&seed_epoch(*e, *l)))
+            .collect();
+        missing.sort_unstable_by_key(|(e, l)| seed_epoch(*e, *l));
+        missing.dedup();
+        // forged headers of many epochs must not have as many caches built.
+        missing.truncate(self.max_dags);
+        let built: Vec<LightDAG> = missing
+            .into_par_iter()
+            .map(|(epoch, length)| {
+                LightDAG::with_epoch_length((epoch * length).into(), length)
+            })
+            .collect();
+        for dag in built {
+            self.insert_dag(dag);
+        }
+
+        let dags = &self.dags;
+        let results = checked
+            .into_par_iter()
+            .zip(headers)
+            .map(|(checked, header)| {
+                let (epoch, length, seal_hash) = checked?;
+                let dag = dags
+                    .get(&seed_epoch(epoch, length))
+                    .ok_or(SealError::UnsupportedEpoch(epoch))?;
+                let mix_hash = dag.hashimoto(seal_hash, header.nonce).0;
+                if mix_hash != header.mix_hash {
+                    return Err(SealError::InvalidMixHash);
+                }
+                Ok(())
+            })
+            .collect();
+
+        // keep the latest epochs, which the next batch most likely needs.
+        while self.dags.len() > self.max_dags {
+            let first = *self.dags.keys().next().unwrap();
+            self.dags.remove(&first);
+        }
+        SealReport { results }
+    }
+}
+
+/// Results of [`SealVerifier::verify`], in the order of the headers.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SealReport {
+    pub results: Vec<Result<(), SealError>>,
+}
+
+#[cfg(feature = "std")]
+impl SealReport {
+    pub fn is_ok(&self) -> bool { self.first_failure().is_none() }
+
+    /// Index and error of the first header failing verification.
+    pub fn first_failure(&self) -> Option<(usize, &SealError)> {
+        self.results
+            .iter()
+            .enumerate()
+            .find_map(|(i, result)| result.as_ref().err().map(|e| (i, e)))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use ethereum_types::U256;
+
+    fn sealed(number: usize, dag: &LightDAG) -> BlockHeader {
+        let header = BlockHeader {
+            difficulty: U256::from(4),
+            number: number.into(),
+            ..Default::default()
+        };
+        header.seal_light(dag, 0..=u64::MAX).unwrap()
+    }
+
+    #[test]
+    fn quick_check() {
+        let dag = LightDAG::tiny(0);
+        let mut header = sealed(1, &dag);
+        assert_eq!(quick_verify_seal(&header), Ok(()));
+        assert_eq!(verify_seal(&header, &dag), Ok(()));
+        let (_, hash) = dag.hashimoto(header.seal_hash(), header.nonce);
+        let quick = crate::quick_hashimoto(
+            header.seal_hash(),
+            header.nonce,
+            header.mix_hash,
+        );
+        assert_eq!(quick, hash);
+
+        // a forged mix digest almost never meets the difficulty.
+        header.difficulty = U256::from(1u64 << 32);
+        header.mix_hash = H256::repeat_byte(0xab);
+        assert_eq!(quick_verify_seal(&header), Err(SealError::InvalidNonce));
+        assert_eq!(verify_seal(&header, &dag), Err(SealError::InvalidNonce));
+        header.difficulty = U256::zero();
+        assert_eq!(quick_verify_seal(&header), Err(SealError::ZeroDifficulty));
+    }
+
+    #[test]
+    fn verify_batch() {
+        let dags = [LightDAG::tiny(0), LightDAG::tiny(1)];
+        let mut headers: Vec<_> = [1, 2, EPOCH_LENGTH, 3, EPOCH_LENGTH + 1]
+            .iter()
+            .map(|n| sealed(*n, &dags[n / EPOCH_LENGTH]))
+            .collect();
+        let mut verifier = SealVerifier::new(2, 1);
+        for dag in dags {
+            verifier.insert_dag(dag);
+        }
+        let valid = headers.clone();
+        assert!(verifier.verify(&valid).is_ok());
+        // only the latest cache is kept.
+        assert_eq!(verifier.dags.keys().collect::<Vec<_>>(), [&1]);
+
+        let mut verifier = SealVerifier::new(2, 2);
+        verifier.insert_dag(LightDAG::tiny(0));
+        verifier.insert_dag(LightDAG::tiny(1));
+        // a difficulty of 1 passes the quick check whatever the mix digest.
+        headers[3].difficulty = U256::one();
+        headers[4].difficulty = U256::MAX;
+        let (seal_hash, nonce) = (headers[4].seal_hash(), headers[4].nonce);
+        headers[4].mix_hash = LightDAG::tiny(1).hashimoto(seal_hash, nonce).0;
+        headers.push(sealed(2 * EPOCH_LENGTH, &LightDAG::tiny(2)));
+        let report = verifier.verify(&headers);
+        let failure = Some((3, &SealError::InvalidMixHash));
+        assert_eq!(report.first_failure(), failure);
+        assert_eq!(
+            report.results,
+            [
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Err(SealError::InvalidMixHash),
+                Err(SealError::InvalidNonce),
+                Err(SealError::UnsupportedEpoch(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_builds_caches_sparingly() {
+        // no cache may be built, so headers without one are unsupported.
+        let mut verifier = SealVerifier::new(2, 0);
+        let mut forged = sealed(1, &LightDAG::tiny(0));
+        forged.difficulty = U256::from(1u64 << 32);
+        forged.mix_hash = H256::repeat_byte(0xab);
+        let headers = [sealed(2, &LightDAG::tiny(0)), forged];
+        assert_eq!(
+            verifier.verify(&headers).results,
+            [Err(SealError::UnsupportedEpoch(0)), Err(SealError::InvalidNonce)]
+        );
+        assert!(verifier.dags.is_empty());
+    }
+}
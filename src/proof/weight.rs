@@ -0,0 +1,131 @@
+//! Chain weight.
+//!
+//! Ethash chains follow the branch of highest total difficulty. Ethereum
+//! Classic additionally applied MESS ([ECBP-1100]) between blocks 11380000
+//! and 19250000: the further a competing branch forks off in the past, the
+//! heavier than the local one it has to be, up to 31 times, which makes
+//! deep reorgs by a private chain prohibitively expensive.
+//!
+//! [ECBP-1100]: https://ecips.ethereumclassic.org/ECIPs/ecip-1100
+
+use core::cmp::Ordering;
+
+use ethereum_types::U256;
+
+use super::types::BlockHeader;
+
+/// Time since the common ancestor, in seconds, past which the ECBP-1100
+/// polynomial is flat.
+pub const ECBP1100_XCAP: u64 = 25_132;
+/// The ECBP-1100 polynomial is scaled by this denominator.
+pub const ECBP1100_DENOMINATOR: u64 = 128;
+const ECBP1100_AMPLITUDE: u64 = 15;
+const ECBP1100_HEIGHT: u64 = ECBP1100_DENOMINATOR * ECBP1100_AMPLITUDE * 2;
+
+/// Sum of the difficulties of `headers`.
+pub fn total_difficulty<'a, I>(headers: I) -> U256
+where
+    I: IntoIterator<Item = &'a BlockHeader>,
+{
+    headers
+        .into_iter()
+        .fold(U256::zero(), |td, header| td.saturating_add(header.difficulty))
+}
+
+/// Compare two branches forking off the same block, by the difficulty they
+/// add on top of it.
+pub fn compare_branches(
+    current: &[BlockHeader],
+    proposed: &[BlockHeader],
+) -> Ordering {
+    total_difficulty(proposed).cmp(&total_difficulty(current))
+}
+
+/// The ECBP-1100 antigravity polynomial, scaled by [`ECBP1100_DENOMINATOR`]:
+/// how many times heavier a branch forking off `x` seconds ago has to be.
+///
+/// `128 + (3x^2 - 2x^3 / xcap) * 3840 / xcap^2`, with `x` capped at
+/// [`ECBP1100_XCAP`], i.e. from 1 up to 31 times heavier.
+pub fn ecbp1100_polynomial(x: u64) -> U256 {
+    let x = x.min(ECBP1100_XCAP);
+    let curve = 3 * x * x - 2 * x * x * x / ECBP1100_XCAP;
+    let height = curve * ECBP1100_HEIGHT / (ECBP1100_XCAP * ECBP1100_XCAP);
+    U256::from(ECBP1100_DENOMINATOR + height)
+}
+
+/// Whether ECBP-1100 lets a branch adding `proposed` difficulty replace one
+/// adding `current`, both on top of a common ancestor that is `age` seconds
+/// older than the proposed head.
+pub fn ecbp1100_allows(age: u64, current: U256, proposed: U256) -> bool {
+    let want = ecbp1100_polynomial(age).full_mul(current);
+    let got = proposed.full_mul(U256::from(ECBP1100_DENOMINATOR));
+    got >= want
+}
+
+/// The branch to follow, out of two forking off `ancestor`: the proposed
+/// one if it is strictly heavier and, with `ecbp1100`, heavy enough for its
+/// age.
+pub fn prefer_proposed(
+    ancestor: &BlockHeader,
+    current: &[BlockHeader],
+    proposed: &[BlockHeader],
+    ecbp1100: bool,
+) -> bool {
+    let (current_td, proposed_td) =
+        (total_difficulty(current), total_difficulty(proposed));
+    if proposed_td <= current_td {
+        return false;
+    }
+    match proposed.last() {
+        Some(head) if ecbp1100 && !current.is_empty() => {
+            let age = head.timestamp.saturating_sub(ancestor.timestamp);
+            ecbp1100_allows(age, current_td, proposed_td)
+        },
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(difficulty: u64, timestamp: u64) -> BlockHeader {
+        BlockHeader {
+            difficulty: difficulty.into(),
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn polynomial() {
+        assert_eq!(ecbp1100_polynomial(0), 128.into());
+        assert_eq!(ecbp1100_polynomial(ECBP1100_XCAP), 3968.into());
+        assert_eq!(ecbp1100_polynomial(u64::MAX), 3968.into());
+        // halfway up the curve at half the cap.
+        assert_eq!(ecbp1100_polynomial(ECBP1100_XCAP / 2), 2048.into());
+        assert!(ecbp1100_allows(0, 100.into(), 100.into()));
+        assert!(!ecbp1100_allows(ECBP1100_XCAP, 100.into(), 3099.into()));
+        assert!(ecbp1100_allows(ECBP1100_XCAP, 100.into(), 3100.into()));
+    }
+
+    #[test]
+    fn fork_choice() {
+        let ancestor = header(10, 0);
+        let current = [header(10, 13), header(10, 26)];
+        let proposed = [header(11, 13), header(10, 26)];
+        assert_eq!(total_difficulty(&current), 20.into());
+        assert_eq!(compare_branches(&current, &proposed), Ordering::Greater);
+        assert_eq!(compare_branches(&current, &current), Ordering::Equal);
+        assert!(prefer_proposed(&ancestor, &current, &proposed, false));
+        assert!(!prefer_proposed(&ancestor, &current, &current, false));
+
+        assert!(!prefer_proposed(&ancestor, &[], &[], true));
+        // a day-old fork has to be 31 times heavier.
+        let heavy = [header(600, 43_200), header(20, 86_400)];
+        assert!(prefer_proposed(&ancestor, &current, &heavy, true));
+        let light = [header(599, 43_200), header(20, 86_400)];
+        assert!(!prefer_proposed(&ancestor, &current, &light, true));
+        assert!(prefer_proposed(&ancestor, &current, &light, false));
+    }
+}
@@ -0,0 +1,567 @@
+//! Stratum mining client.
+//!
+//! [`StratumClient`] logs into a pool and mines its jobs with a fixed set of
+//! worker threads. A job names its epoch by seed hash only, which is mapped
+//! back to the epoch, of the length the chain config of the client gives it,
+//! to build the light cache the job is hashed with. New
+//! jobs, share difficulties and extranonces are handed to the running
+//! threads, which pick them up before their next nonce.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use ethereum_types::{BigEndianHash, H256, H64, U256};
+use parking_lot::RwLock;
+use serde_json::{json, Value};
+
+use super::{
+    from_hex, from_stratum_difficulty, h256_param, to_hex, Dialect,
+    STRATUM_VERSION,
+};
+use crate::chain::ChainConfig;
+use crate::{LightDAG, EPOCH_LENGTH};
+
+// Ids of the login requests, submissions use the following ones.
+const SUBSCRIBE_ID: u64 = 1;
+const AUTHORIZE_ID: u64 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// Chain the jobs are blocks of, which sets the lengths of their epochs.
+    pub chain: ChainConfig,
+    pub dialect: Dialect,
+    /// Worker name, usually `<account>.<rig>`.
+    pub worker: String,
+    pub password: String,
+    /// Number of mining threads.
+    pub threads: usize,
+    /// Number of seed hashes a seed hash is looked up among.
+    pub max_epochs: usize,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            chain: ChainConfig::default(),
+            dialect: Dialect::EthereumStratum,
+            worker: "ethash".into(),
+            password: "x".into(),
+            threads: num_cpus::get(),
+            max_epochs: 2048,
+        }
+    }
+}
+
+/// Counts of the shares answered by the pool.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+/// The job mined by the worker threads.
+struct Work {
+    job_id: String,
+    header_hash: H256,
+    dag: Arc<LightDAG>,
+    boundary: U256,
+    // Leading bytes of every nonce, assigned by the pool.
+    extranonce: Vec<u8>,
+    // Bumped whenever the job changes, which restarts the search.
+    generation: u64,
+}
+
+struct Solution {
+    job_id: String,
+    header_hash: H256,
+    nonce: H64,
+    mix_hash: H256,
+    extranonce_size: usize,
+}
+
+enum Event {
+    Message(Value),
+    Solution(Solution),
+    Closed,
+}
+
+/// Shared between the client and its worker threads.
+#[derive(Default)]
+struct Shared {
+    work: RwLock<Option<Arc<Work>>>,
+    stop: AtomicBool,
+}
+
+/// A connection to a pool, mined by [`StratumClient::run`].
+pub struct StratumClient {
+    config: ClientConfig,
+    writer: TcpStream,
+    events: Receiver<Event>,
+    solutions: Sender<Event>,
+    shared: Arc<Shared>,
+    // DAGs by seed epoch.
+    dags: HashMap<usize, Arc<LightDAG>>,
+    extranonce: Vec<u8>,
+    boundary: U256,
+    // Job of the latest notification, kept to republish it with a new
+    // boundary or extranonce.
+    job: Option<(String, H256, Arc<LightDAG>)>,
+    next_id: u64,
+    submissions: HashSet<u64>,
+    stats: ClientStats,
+}
+
+impl StratumClient {
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        config: ClientConfig,
+    ) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let writer = stream.try_clone()?;
+        let (sender, events) = mpsc::channel();
+        let messages = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let message = match serde_json::from_str(&line) {
+                    Ok(message) => message,
+                    Err(_) => break,
+                };
+                if messages.send(Event::Message(message)).is_err() {
+                    return;
+                }
+            }
+            let _ = messages.send(Event::Closed);
+        });
+
+        Ok(Self {
+            config,
+            writer,
+            events,
+            solutions: sender,
+            shared: Arc::new(Shared::default()),
+            dags: HashMap::new(),
+            extranonce: Vec::new(),
+            boundary: crate::cross_boundary(U256::from(1u64 << 32)),
+            job: None,
+            next_id: AUTHORIZE_ID + 1,
+            submissions: HashSet::new(),
+            stats: ClientStats::default(),
+        })
+    }
+
+    /// Mine the jobs of the epoch of `dag` with it, instead of building the
+    /// cache when the first job of the epoch is notified.
+    pub fn insert_dag(&mut self, dag: LightDAG) {
+        self.dags.insert(dag.seed_epoch(), Arc::new(dag));
+    }
+
+    /// A flag stopping [`StratumClient::run`] once set.
+    pub fn stop_handle(&self) -> StopHandle { StopHandle(self.shared.clone()) }
+
+    /// Log in and mine until the connection is closed or the client is
+    /// stopped.
+    pub fn run(mut self) -> io::Result<ClientStats> {
+        let workers: Vec<_> = (0..self.config.threads.max(1))
+            .map(|index| {
+                let shared = self.shared.clone();
+                let solutions = self.solutions.clone();
+                let threads = self.config.threads.max(1) as u64;
+                thread::spawn(move || {
+                    mine(&shared, index as u64, threads, &solutions)
+                })
+            })
+            .collect();
+
+        let result = self.login().and_then(|_| self.serve());
+        self.shared.stop.store(true, Ordering::SeqCst);
+        for worker in workers {
+            let _ = worker.join();
+        }
+        result.map(|_| self.stats)
+    }
+
+    fn login(&mut self) -> io::Result<()> {
+        let (worker, password) = (&self.config.worker, &self.config.password);
+        let requests = match self.config.dialect {
+            Dialect::EthereumStratum => [
+                json!({
+                    "id": SUBSCRIBE_ID,
+                    "method": "mining.subscribe",
+                    "params": ["ethash-rs", STRATUM_VERSION],
+                }),
+                json!({
+                    "id": AUTHORIZE_ID,
+                    "method": "mining.authorize",
+                    "params": [worker, password],
+                }),
+            ],
+            Dialect::EthProxy => [
+                json!({
+                    "id": AUTHORIZE_ID,
+                    "method": "eth_submitLogin",
+                    "params": [worker, password],
+                }),
+                json!({
+                    "id": SUBSCRIBE_ID,
+                    "method": "eth_getWork",
+                    "params": [],
+                }),
+            ],
+        };
+        requests.iter().try_for_each(|request| self.send(request))
+    }
+
+    fn serve(&mut self) -> io::Result<()> {
+        while !self.shared.stop.load(Ordering::SeqCst) {
+            match self.events.recv_timeout(Duration::from_millis(100)) {
+                Ok(Event::Message(message)) => self.on_message(&message)?,
+                Ok(Event::Solution(solution)) => self.submit(solution)?,
+                Ok(Event::Closed) | Err(RecvTimeoutError::Disconnected) => {
+                    break
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, message: &Value) -> io::Result<()> {
+        writeln!(self.writer, "{}", message)
+    }
+
+    fn on_message(&mut self, message: &Value) -> io::Result<()> {
+        let params = &message["params"];
+        match message["method"].as_str() {
+            Some("mining.notify") => {
+                let job_id = params[0].as_str().unwrap_or_default();
+                let seed_hash = h256_param(&params[1]);
+                let header_hash = h256_param(&params[2]);
+                match (seed_hash, header_hash) {
+                    (Some(seed), Some(header)) => {
+                        self.set_job(job_id.into(), seed, header)
+                    },
+                    _ => Err(invalid("malformed mining.notify")),
+                }
+            },
+            Some("mining.set_difficulty") => {
+                let difficulty = params[0].as_f64().unwrap_or(1.0);
+                self.boundary =
+                    crate::cross_boundary(from_stratum_difficulty(difficulty));
+                self.publish();
+                Ok(())
+            },
+            Some("mining.set_extranonce") => {
+                self.set_extranonce(&params[0])?;
+                self.publish();
+                Ok(())
+            },
+            Some(_) => Ok(()),
+            None => self.on_response(message),
+        }
+    }
+
+    fn on_response(&mut self, message: &Value) -> io::Result<()> {
+        let id = message["id"].as_u64();
+        let ok = message["error"].is_null() && message["result"] != false;
+        match (self.config.dialect, id) {
+            (_, Some(id)) if self.submissions.remove(&id) => {
+                if ok {
+                    self.stats.accepted += 1;
+                } else {
+                    self.stats.rejected += 1;
+                }
+                Ok(())
+            },
+            (_, Some(AUTHORIZE_ID)) if !ok => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "login"))
+            },
+            (Dialect::EthereumStratum, Some(SUBSCRIBE_ID)) if ok => {
+                self.set_extranonce(&message["result"][1])
+            },
+            // new work is pushed as a response to request 0.
+            (Dialect::EthProxy, Some(0) | Some(SUBSCRIBE_ID)) if ok => {
+                let work = &message["result"];
+                let seed_hash = h256_param(&work[1]);
+                let header_hash = h256_param(&work[0]);
+                let boundary = work[2].as_str().and_then(from_hex::<32>);
+                match (seed_hash, header_hash, boundary) {
+                    (Some(seed), Some(header), Some(boundary)) => {
+                        self.boundary = U256::from_big_endian(&boundary);
+                        let job_id = to_hex(header.as_bytes(), true);
+                        self.set_job(job_id, seed, header)
+                    },
+                    _ => Err(invalid("malformed work package")),
+                }
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn set_extranonce(&mut self, value: &Value) -> io::Result<()> {
+        let extranonce = value.as_str().unwrap_or_default();
+        let extranonce = hex::decode(extranonce)
+            .map_err(|_| invalid("malformed extranonce"))?;
+        if extranonce.len() >= 8 {
+            return Err(invalid("extranonce leaves no nonce"));
+        }
+        self.extranonce = extranonce;
+        Ok(())
+    }
+
+    fn set_job(
+        &mut self,
+        job_id: String,
+        seed_hash: H256,
+        header_hash: H256,
+    ) -> io::Result<()> {
+        let seed_epoch =
+            crate::get_epoch_from_seedhash(seed_hash, self.config.max_epochs)
+                .ok_or_else(|| invalid("unknown seed hash"))?;
+        // the first block of the seed hash, whatever the length of its epoch.
+        let number = (seed_epoch * EPOCH_LENGTH) as u64;
+        let chain = &self.config.chain;
+        let dag = self
+            .dags
+            .entry(seed_epoch)
+            .or_insert_with(|| Arc::new(chain.light_dag(number)))
+            .clone();
+        self.dags.retain(|e, _| *e == seed_epoch);
+        self.job = Some((job_id, header_hash, dag));
+        self.publish();
+        Ok(())
+    }
+
+    /// Hand the current job to the worker threads, which only restart their
+    /// search if the job changed.
+    fn publish(&mut self) {
+        let (job_id, header_hash, dag) = match &self.job {
+            Some(job) => job.clone(),
+            None => return,
+        };
+        let same_job = |work: &Work| {
+            work.job_id == job_id && work.header_hash == header_hash
+        };
+        let mut work = self.shared.work.write();
+        let generation = match work.as_deref() {
+            Some(work) if same_job(work) => work.generation,
+            Some(work) => work.generation + 1,
+            None => 0,
+        };
+        *work = Some(Arc::new(Work {
+            job_id,
+            header_hash,
+            dag,
+            boundary: self.boundary,
+            extranonce: self.extranonce.clone(),
+            generation,
+        }));
+    }
+
+    fn submit(&mut self, solution: Solution) -> io::Result<()> {
+        // solutions found right before a job switch are stale.
+        let current = self.job.as_ref().map(|(id, ..)| id.as_str());
+        if current != Some(solution.job_id.as_str()) {
+            return Ok(());
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = match self.config.dialect {
+            Dialect::EthereumStratum => json!({
+                "id": id,
+                "method": "mining.submit",
+                "params": [
+                    self.config.worker,
+                    solution.job_id,
+                    to_hex(&solution.nonce[solution.extranonce_size..], false),
+                ],
+            }),
+            Dialect::EthProxy => json!({
+                "id": id,
+                "method": "eth_submitWork",
+                "params": [
+                    to_hex(solution.nonce.as_bytes(), true),
+                    to_hex(solution.header_hash.as_bytes(), true),
+                    to_hex(solution.mix_hash.as_bytes(), true),
+                ],
+            }),
+        };
+        self.submissions.insert(id);
+        self.send(&request)
+    }
+}
+
+/// Stops a running [`StratumClient`].
+#[derive(Clone)]
+pub struct StopHandle(Arc<Shared>);
+
+impl StopHandle {
+    pub fn stop(&self) { self.0.stop.store(true, Ordering::SeqCst) }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Worker thread: search the nonces `index + k * threads` after the
+/// extranonce, picking up the latest work before every nonce. The search
+/// restarts on new jobs only: a new boundary or extranonce carries on with
+/// the next nonces.
+fn mine(shared: &Shared, index: u64, threads: u64, solutions: &Sender<Event>) {
+    let mut generation = None;
+    let mut counter = index;
+    while !shared.stop.load(Ordering::SeqCst) {
+        let work = match shared.work.read().clone() {
+            Some(work) => work,
+            None => {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            },
+        };
+        if generation != Some(work.generation) {
+            generation = Some(work.generation);
+            counter = index;
+        }
+        let prefix = work.extranonce.len();
+        let mut nonce = [0u8; 8];
+        nonce[..prefix].copy_from_slice(&work.extranonce);
+        nonce[prefix..].copy_from_slice(&counter.to_be_bytes()[prefix..]);
+        let (mix_hash, hash) = work.dag.hashimoto(work.header_hash, H64(nonce));
+        if hash.into_uint() <= work.boundary {
+            let solution = Solution {
+                job_id: work.job_id.clone(),
+                header_hash: work.header_hash,
+                nonce: H64(nonce),
+                mix_hash,
+                extranonce_size: prefix,
+            };
+            if solutions.send(Event::Solution(solution)).is_err() {
+                return;
+            }
+        }
+        counter = counter.wrapping_add(threads);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::server::{Job, ServerConfig, Share, StratumServer};
+    use super::*;
+
+    fn job(id: &str, header_hash: H256) -> Job {
+        Job {
+            id: id.into(),
+            header_hash,
+            number: 0,
+            difficulty: U256::MAX,
+        }
+    }
+
+    fn next_share(shares: &Receiver<Share>) -> Share {
+        shares.recv_timeout(Duration::from_secs(60)).unwrap()
+    }
+
+    fn mine_jobs(dialect: Dialect) {
+        let config = ServerConfig {
+            difficulty: U256::from(8),
+            ..ServerConfig::default()
+        };
+        let (server, shares) =
+            StratumServer::bind("127.0.0.1:0", config).unwrap();
+        server.insert_dag(LightDAG::tiny(0));
+        server.notify(job("1", H256::repeat_byte(1)), true);
+
+        let config = ClientConfig {
+            dialect,
+            worker: "w".into(),
+            threads: 2,
+            ..ClientConfig::default()
+        };
+        let mut client =
+            StratumClient::connect(server.local_addr(), config).unwrap();
+        client.insert_dag(LightDAG::tiny(0));
+        let stop = client.stop_handle();
+        let running = thread::spawn(move || client.run());
+
+        for _ in 0..2 {
+            let share = next_share(&shares);
+            assert_eq!(share.worker, "w");
+            assert_eq!(share.job_id, "1");
+        }
+        // the workers move on to the new job.
+        server.notify(job("2", H256::repeat_byte(2)), true);
+        while next_share(&shares).job_id != "2" {}
+
+        stop.stop();
+        let stats = running.join().unwrap().unwrap();
+        assert!(stats.accepted >= 1, "{:?}", stats);
+    }
+
+    #[test]
+    fn restarts_on_new_jobs_only() {
+        let dag = Arc::new(LightDAG::tiny(0));
+        let work = |job_id: &str, extranonce: Vec<u8>, generation| {
+            Some(Arc::new(Work {
+                job_id: job_id.into(),
+                header_hash: H256::zero(),
+                dag: dag.clone(),
+                boundary: U256::MAX,
+                extranonce,
+                generation,
+            }))
+        };
+        let shared = Arc::new(Shared::default());
+        *shared.work.write() = work("1", Vec::new(), 0);
+        let (sender, solutions) = mpsc::channel();
+        let worker = {
+            let shared = shared.clone();
+            thread::spawn(move || mine(&shared, 0, 1, &sender))
+        };
+        let next = || match solutions.recv().unwrap() {
+            Event::Solution(solution) => (solution.job_id, solution.nonce),
+            _ => unreachable!(),
+        };
+        assert_eq!(next().1, H64::from_low_u64_be(0));
+        assert_eq!(next().1, H64::from_low_u64_be(1));
+
+        // a new extranonce for the same job goes on with the next nonces.
+        *shared.work.write() = work("1", vec![0xff], 0);
+        let nonce = loop {
+            let (_, nonce) = next();
+            if nonce[0] == 0xff {
+                break nonce;
+            }
+        };
+        assert!(nonce.to_low_u64_be() & ((1 << 56) - 1) > 1);
+
+        // a new job starts over.
+        *shared.work.write() = work("2", vec![0xff], 1);
+        let nonce = loop {
+            let (job_id, nonce) = next();
+            if job_id == "2" {
+                break nonce;
+            }
+        };
+        assert_eq!(nonce, H64::from_low_u64_be(0xff << 56));
+        shared.stop.store(true, Ordering::SeqCst);
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn mines_ethereum_stratum_jobs() { mine_jobs(Dialect::EthereumStratum) }
+
+    #[test]
+    fn mines_eth_proxy_jobs() { mine_jobs(Dialect::EthProxy) }
+}
@@ -0,0 +1,491 @@
+//! `eth_getWork` JSON-RPC endpoint.
+//!
+//! The remote mining interface of the Ethereum nodes, served over HTTP:
+//! miners poll `eth_getWork` for the seal hash, seed hash and boundary of the
+//! block being mined, and hand solutions back with `eth_submitWork`. Headers
+//! to be mined are handed to the server with [`GetWorkServer::set_work`], and
+//! the headers sealed by valid solutions are sent down the channel returned
+//! by [`GetWorkServer::bind`].
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ethereum_types::{BigEndianHash, H256, H64, U256};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+
+use super::{boundary_hex, h256_param, h64_param, to_hex};
+use crate::chain::ChainConfig;
+use crate::types::BlockHeader;
+use crate::LightDAG;
+
+/// Number of recent headers solutions are accepted for.
+const MAX_WORKS: usize = 8;
+
+/// Longest request body accepted, in bytes.
+const MAX_BODY: usize = 64 * 1024;
+
+/// Longest request line and headers accepted, in bytes, all together.
+const MAX_HEADERS: usize = 8 * 1024;
+
+/// Hashrates not reported again within this delay are dropped.
+const HASHRATE_TTL: Duration = Duration::from_secs(10);
+
+struct Work {
+    seal_hash: H256,
+    // Index of the seed hash of the header, which keys its DAG.
+    seed_epoch: usize,
+    header: BlockHeader,
+    // Nonces of the solutions accepted so far.
+    solved: HashSet<H64>,
+}
+
+struct State {
+    // Recent headers.
+    works: VecDeque<Work>,
+    // DAGs by seed epoch.
+    dags: HashMap<usize, Arc<LightDAG>>,
+    hashrates: HashMap<H256, (U256, Instant)>,
+    sealed: Sender<BlockHeader>,
+}
+
+/// A getwork server answering requests in the background.
+pub struct GetWorkServer {
+    state: Arc<Mutex<State>>,
+    local_addr: SocketAddr,
+    chain: ChainConfig,
+}
+
+impl GetWorkServer {
+    /// Listen on `addr`, returning the server and the channel of the sealed
+    /// headers. Headers are blocks of `chain`, which sets the lengths of
+    /// their epochs.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        chain: ChainConfig,
+    ) -> io::Result<(Self, Receiver<BlockHeader>)> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let (sealed, receiver) = mpsc::channel();
+        let state = Arc::new(Mutex::new(State {
+            works: VecDeque::new(),
+            dags: HashMap::new(),
+            hashrates: HashMap::new(),
+            sealed,
+        }));
+
+        let accepting = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = accepting.clone();
+                thread::spawn(move || serve(&state, stream));
+            }
+        });
+        let server = Self {
+            state,
+            local_addr,
+            chain,
+        };
+        Ok((server, receiver))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr { self.local_addr }
+
+    /// Use `dag` to verify the solutions of its epoch, instead of building it
+    /// when a header of the epoch is set.
+    pub fn insert_dag(&self, dag: LightDAG) {
+        let seed_epoch = dag.seed_epoch();
+        self.state.lock().dags.insert(seed_epoch, Arc::new(dag));
+    }
+
+    /// Hand out `header` to be mined, its `mix_hash` and `nonce` aside.
+    ///
+    /// Blocks while the cache of a new epoch is built.
+    pub fn set_work(&self, header: BlockHeader) {
+        let number = header.number.low_u64();
+        let seed_epoch = self.chain.seed_epoch(number);
+        let has_dag = self.state.lock().dags.contains_key(&seed_epoch);
+        let dag = (!has_dag).then(|| Arc::new(self.chain.light_dag(number)));
+
+        let mut state = self.state.lock();
+        if let Some(dag) = dag {
+            state.dags.insert(seed_epoch, dag);
+        }
+        state.works.push_back(Work {
+            seal_hash: header.seal_hash(),
+            seed_epoch,
+            header,
+            solved: HashSet::new(),
+        });
+        while state.works.len() > MAX_WORKS {
+            state.works.pop_front();
+        }
+        let State { works, dags, .. } = &mut *state;
+        dags.retain(|e, _| works.iter().any(|w| w.seed_epoch == *e));
+    }
+
+    /// Sum of the hashrates recently reported by the miners.
+    pub fn hashrate(&self) -> U256 { self.state.lock().hashrate() }
+}
+
+impl State {
+    fn hashrate(&mut self) -> U256 {
+        self.hashrates
+            .retain(|_, (_, reported)| reported.elapsed() < HASHRATE_TTL);
+        self.hashrates
+            .values()
+            .fold(U256::zero(), |total, (rate, _)| total.saturating_add(*rate))
+    }
+}
+
+fn serve(state: &Mutex<State>, stream: TcpStream) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    while let Ok(Some(body)) = read_request(&mut reader) {
+        let reply = match serde_json::from_slice::<Value>(&body) {
+            Ok(request) => {
+                let result = handle(state, &request);
+                response(&request["id"], result)
+            },
+            Err(_) => response(&Value::Null, Err((-32700, "parse error"))),
+        };
+        let body = reply.to_string();
+        let written = write!(
+            writer,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        if written.is_err() {
+            break;
+        }
+    }
+}
+
+/// Read the body of the next HTTP request, `None` once the connection is
+/// closed.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidData, "size");
+    let mut head = reader.take(MAX_HEADERS as u64);
+    let mut line = String::new();
+    if head.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut length = 0;
+    loop {
+        // the headers do not end within the bytes left.
+        if head.limit() == 0 {
+            return Err(too_large());
+        }
+        line.clear();
+        if head.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "length")
+                })?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(too_large());
+    }
+    let mut body = Vec::with_capacity(length);
+    head.into_inner().take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Some(body))
+}
+
+/// JSON-RPC error code and message.
+type RpcError = (i64, &'static str);
+
+const INVALID_PARAMS: RpcError = (-32602, "invalid params");
+
+fn handle(state: &Mutex<State>, request: &Value) -> Result<Value, RpcError> {
+    let params = &request["params"];
+    match request["method"].as_str().unwrap_or_default() {
+        "eth_getWork" => {
+            let state = state.lock();
+            let Work {
+                seal_hash,
+                seed_epoch,
+                header,
+                ..
+            } = state
+                .works
+                .back()
+                .ok_or((-32000, "no mining work available yet"))?;
+            let boundary = crate::cross_boundary(header.difficulty);
+            let seed_hash = crate::get_seedhash(*seed_epoch);
+            Ok(json!([
+                to_hex(seal_hash.as_bytes(), true),
+                to_hex(seed_hash.as_bytes(), true),
+                boundary_hex(boundary),
+                format!("{:#x}", header.number),
+            ]))
+        },
+        "eth_submitWork" => submit_work(state, params),
+        "eth_submitHashrate" => {
+            let rate = params[0].as_str().and_then(parse_quantity);
+            match (rate, h256_param(&params[1])) {
+                (Some(rate), Some(id)) => {
+                    let now = Instant::now();
+                    state.lock().hashrates.insert(id, (rate, now));
+                    Ok(Value::Bool(true))
+                },
+                _ => Err(INVALID_PARAMS),
+            }
+        },
+        "eth_hashrate" => Ok(json!(format!("{:#x}", state.lock().hashrate()))),
+        _ => Err((-32601, "method not found")),
+    }
+}
+
+/// `eth_submitWork` params: nonce, seal hash and mix hash. Solutions that
+/// are not valid for a recent header, or were accepted already, are refused
+/// with `false`.
+fn submit_work(
+    state: &Mutex<State>,
+    params: &Value,
+) -> Result<Value, RpcError> {
+    let nonce = h64_param(&params[0]).ok_or(INVALID_PARAMS)?;
+    let seal_hash = h256_param(&params[1]).ok_or(INVALID_PARAMS)?;
+    let mix_hash = h256_param(&params[2]).ok_or(INVALID_PARAMS)?;
+    let work = {
+        let state = state.lock();
+        let work = state.works.iter().find(|w| w.seal_hash == seal_hash);
+        work.map(|work| {
+            let dag = state.dags[&work.seed_epoch].clone();
+            (work.header.clone(), dag)
+        })
+    };
+    let (mut header, dag) = match work {
+        Some(work) => work,
+        None => return Ok(Value::Bool(false)),
+    };
+
+    let (mix, hash) = dag.hashimoto(seal_hash, nonce);
+    let boundary = crate::cross_boundary(header.difficulty);
+    if mix != mix_hash || hash.into_uint() > boundary {
+        return Ok(Value::Bool(false));
+    }
+    header.mix_hash = mix;
+    header.nonce = nonce;
+    let mut state = state.lock();
+    // the work may have been dropped while the solution was checked.
+    let work = state.works.iter_mut().find(|w| w.seal_hash == seal_hash);
+    if !work.is_some_and(|work| work.solved.insert(nonce)) {
+        return Ok(Value::Bool(false));
+    }
+    // nobody listening for blocks is not an error of the miner.
+    let _ = state.sealed.send(header);
+    Ok(Value::Bool(true))
+}
+
+fn parse_quantity(s: &str) -> Option<U256> {
+    let digits = s.strip_prefix("0x")?;
+    U256::from_str_radix(digits, 16).ok()
+}
+
+fn response(id: &Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethereum_types::H64;
+
+    use super::*;
+
+    /// A chain stand-in: builds the next header on top of its head.
+    struct Chain {
+        head: BlockHeader,
+    }
+
+    impl Chain {
+        fn pending(&self) -> BlockHeader {
+            BlockHeader {
+                parent_hash: self.head.hash(),
+                number: self.head.number + 1,
+                difficulty: U256::from(4),
+                timestamp: self.head.timestamp + 13,
+                ..self.head.clone()
+            }
+        }
+
+        fn import(&mut self, header: BlockHeader, dag: &LightDAG) {
+            assert_eq!(header.parent_hash, self.head.hash());
+            let (mix_hash, hash) =
+                dag.hashimoto(header.seal_hash(), header.nonce);
+            assert_eq!(mix_hash, header.mix_hash);
+            let boundary = crate::cross_boundary(header.difficulty);
+            assert!(hash.into_uint() <= boundary);
+            self.head = header;
+        }
+    }
+
+    /// A remote miner stand-in.
+    struct Miner {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Miner {
+        fn connect(server: &GetWorkServer) -> Self {
+            let stream = TcpStream::connect(server.local_addr()).unwrap();
+            Self {
+                writer: stream.try_clone().unwrap(),
+                reader: BufReader::new(stream),
+            }
+        }
+
+        fn call(&mut self, method: &str, params: Value) -> Value {
+            let body = json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            })
+            .to_string();
+            write!(
+                self.writer,
+                "POST / HTTP/1.1\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            // a response has the layout of a request.
+            let body = read_request(&mut self.reader).unwrap().unwrap();
+            serde_json::from_slice(&body).unwrap()
+        }
+    }
+
+    #[test]
+    fn remote_mining() {
+        let (server, sealed) =
+            GetWorkServer::bind("127.0.0.1:0", ChainConfig::default())
+                .unwrap();
+        let dag = LightDAG::tiny(0);
+        server.insert_dag(LightDAG::tiny(0));
+        let mut chain = Chain {
+            head: BlockHeader {
+                difficulty: U256::one(),
+                gas_limit: 5000,
+                ..Default::default()
+            },
+        };
+
+        let mut miner = Miner::connect(&server);
+        let reply = miner.call("eth_getWork", json!([]));
+        assert_eq!(reply["error"]["code"], -32000);
+
+        for _ in 0..2 {
+            let pending = chain.pending();
+            server.set_work(pending.clone());
+            let work = miner.call("eth_getWork", json!([]));
+            let work = &work["result"];
+            assert_eq!(work[0], to_hex(pending.seal_hash().as_bytes(), true));
+            assert_eq!(work[1], to_hex(&[0; 32], true));
+            assert_eq!(work[3], format!("{:#x}", pending.number));
+
+            let seal_hash = h256_param(&work[0]).unwrap();
+            let boundary = U256::from_big_endian(
+                &super::super::from_hex::<32>(work[2].as_str().unwrap())
+                    .unwrap(),
+            );
+            let (nonce, mix_hash) = (0..)
+                .map(H64::from_low_u64_be)
+                .find_map(|nonce| {
+                    let (mix_hash, hash) = dag.hashimoto(seal_hash, nonce);
+                    (hash.into_uint() <= boundary).then_some((nonce, mix_hash))
+                })
+                .unwrap();
+            let submit = |mix: H256| {
+                json!([
+                    to_hex(nonce.as_bytes(), true),
+                    to_hex(seal_hash.as_bytes(), true),
+                    to_hex(mix.as_bytes(), true),
+                ])
+            };
+            let reply = miner.call("eth_submitWork", submit(H256::zero()));
+            assert_eq!(reply["result"], false);
+            let reply = miner.call("eth_submitWork", submit(mix_hash));
+            assert_eq!(reply["result"], true);
+            let reply = miner.call("eth_submitWork", submit(mix_hash));
+            assert_eq!(reply["result"], false);
+
+            let header = sealed.try_recv().unwrap();
+            assert_eq!((header.nonce, header.mix_hash), (nonce, mix_hash));
+            assert!(sealed.try_recv().is_err());
+            chain.import(header, &dag);
+        }
+        assert_eq!(chain.head.number, U256::from(2));
+
+        let id = to_hex(&[1; 32], true);
+        let reply = miner.call("eth_submitHashrate", json!(["0x10", id]));
+        assert_eq!(reply["result"], true);
+        let id = to_hex(&[2; 32], true);
+        miner.call("eth_submitHashrate", json!(["0x20", id]));
+        assert_eq!(server.hashrate(), U256::from(0x30));
+        let reply = miner.call("eth_hashrate", json!([]));
+        assert_eq!(reply["result"], "0x30");
+    }
+
+    #[test]
+    fn size_limits() {
+        let request = |length: usize, body: &str| {
+            let request = format!(
+                "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                length, body
+            );
+            read_request(&mut io::Cursor::new(request))
+        };
+        assert_eq!(request(2, "{}").unwrap(), Some(b"{}".to_vec()));
+        let error = request(MAX_BODY + 1, "{}").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = request(usize::MAX, "{}").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = request(3, "{}").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // so are the request line and the headers.
+        let head = |head: String| {
+            let request = format!("{}\r\nContent-Length: 0\r\n\r\n", head);
+            read_request(&mut io::Cursor::new(request))
+        };
+        let path = "a".repeat(MAX_HEADERS / 2);
+        assert!(head(format!("POST /{} HTTP/1.1", path)).is_ok());
+        let path = "a".repeat(MAX_HEADERS);
+        let error = head(format!("POST /{} HTTP/1.1", path)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let headers = "X-Padding: 0\r\n".repeat(MAX_HEADERS / 14);
+        let line = format!("POST / HTTP/1.1\r\n{}", headers);
+        let error = head(line).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,167 @@
+//! Stratum mining protocols.
+//!
+//! Two dialects of JSON-RPC over TCP (one JSON object per line) are spoken:
+//!
+//! - [NiceHash EthereumStratum/1.0][nicehash]: the pool assigns every
+//!   connection an extranonce, which is the first bytes of the nonces it
+//!   submits, and a share difficulty with `mining.set_difficulty`. Jobs are
+//!   `(job id, seed hash, header hash)` triples sent with `mining.notify`.
+//! - ETHPROXY: `eth_submitLogin`, `eth_getWork` and `eth_submitWork` as in the
+//!   node RPC interface, with new work pushed to the miner as a response to
+//!   the request with id 0.
+//!
+//! A stratum difficulty of 1 is a network difficulty of `2^32`.
+//!
+//! [nicehash]: https://github.com/nicehash/Specifications/blob/master/EthereumStratum_NiceHash_v1.0.0.txt
+
+use ethereum_types::{H256, H64, U256};
+use serde_json::Value;
+
+pub mod client;
+pub mod getwork;
+pub mod server;
+
+/// Version string of the NiceHash dialect, sent with `mining.subscribe`.
+pub const STRATUM_VERSION: &str = "EthereumStratum/1.0.0";
+
+/// Network difficulty of a share of stratum difficulty 1.
+const DIFFICULTY_ONE: f64 = 4294967296.0;
+
+/// The protocol spoken on a connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dialect {
+    EthereumStratum,
+    EthProxy,
+}
+
+/// Reasons a share is rejected, with the NiceHash error codes.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ShareError {
+    // Job is unknown or was replaced by a clean job
+    Stale,
+    // The nonce was already submitted for the job
+    Duplicate,
+    // Share hash is above the share boundary
+    LowDifficulty,
+    // Worker did not authorize
+    Unauthorized,
+    // Connection did not subscribe
+    NotSubscribed,
+    // Mix hash does not match the nonce
+    InvalidMixHash,
+    // Parameters of the request are malformed
+    Malformed,
+    // Every extranonce is taken by a live connection
+    NoExtranonce,
+}
+
+impl ShareError {
+    pub fn code(&self) -> i64 {
+        match self {
+            ShareError::Stale => 21,
+            ShareError::Duplicate => 22,
+            ShareError::LowDifficulty => 23,
+            ShareError::Unauthorized => 24,
+            ShareError::NotSubscribed => 25,
+            ShareError::InvalidMixHash
+            | ShareError::Malformed
+            | ShareError::NoExtranonce => 20,
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            ShareError::Stale => "Job not found",
+            ShareError::Duplicate => "Duplicate share",
+            ShareError::LowDifficulty => "Low difficulty share",
+            ShareError::Unauthorized => "Unauthorized worker",
+            ShareError::NotSubscribed => "Not subscribed",
+            ShareError::InvalidMixHash => "Invalid mix hash",
+            ShareError::Malformed => "Malformed request",
+            ShareError::NoExtranonce => "No extranonce left",
+        }
+    }
+}
+
+/// The stratum difficulty of a network `difficulty`.
+pub fn to_stratum_difficulty(difficulty: U256) -> f64 {
+    let value = difficulty
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |acc, limb| acc * 18446744073709551616.0 + *limb as f64);
+    value / DIFFICULTY_ONE
+}
+
+/// The network difficulty of a stratum `difficulty`, rounded down and at
+/// least 1.
+pub fn from_stratum_difficulty(difficulty: f64) -> U256 {
+    let value = difficulty * DIFFICULTY_ONE;
+    if value.is_nan() || value < 1.0 {
+        return U256::one();
+    }
+    // `f64` has 53 bits of mantissa: shift it into place.
+    let exponent = value.log2().floor() as u32;
+    match exponent.checked_sub(63) {
+        None => U256::from(value as u64),
+        Some(shift) => {
+            let mantissa = (value / 2f64.powi(shift as i32)) as u64;
+            U256::from(mantissa) << shift
+        },
+    }
+}
+
+/// Encode `bytes` as lowercase hex, with or without a `0x` prefix.
+pub(crate) fn to_hex(bytes: &[u8], prefix: bool) -> String {
+    if prefix {
+        format!("0x{}", hex::encode(bytes))
+    } else {
+        hex::encode(bytes)
+    }
+}
+
+/// Decode a hex string of exactly `N` bytes, with or without a `0x` prefix.
+pub(crate) fn from_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let mut bytes = [0u8; N];
+    hex::decode_to_slice(s, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+pub(crate) fn h256_param(value: &Value) -> Option<H256> {
+    value.as_str().and_then(from_hex::<32>).map(H256)
+}
+
+pub(crate) fn h64_param(value: &Value) -> Option<H64> {
+    value.as_str().and_then(from_hex::<8>).map(H64)
+}
+
+pub(crate) fn boundary_hex(boundary: U256) -> String {
+    let mut bytes = [0u8; 32];
+    boundary.to_big_endian(&mut bytes);
+    to_hex(&bytes, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stratum_difficulty() {
+        assert_eq!(to_stratum_difficulty(U256::from(1u64 << 32)), 1.0);
+        assert_eq!(to_stratum_difficulty(U256::from(3u64 << 31)), 1.5);
+        assert_eq!(from_stratum_difficulty(1.5), U256::from(3u64 << 31));
+        assert_eq!(from_stratum_difficulty(0.0), U256::one());
+        let big = U256::from(0x1234_5678u64) << 100;
+        assert_eq!(from_stratum_difficulty(to_stratum_difficulty(big)), big);
+    }
+
+    #[test]
+    fn hex_params() {
+        assert_eq!(to_hex(&[0, 0xab], true), "0x00ab");
+        assert_eq!(from_hex::<2>("00ab"), Some([0, 0xab]));
+        assert_eq!(from_hex::<2>("0x00ab"), Some([0, 0xab]));
+        assert_eq!(from_hex::<2>("0x0ab"), None);
+        assert_eq!(from_hex::<1>("zz"), None);
+    }
+}
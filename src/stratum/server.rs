@@ -0,0 +1,850 @@
+//! Stratum pool server.
+//!
+//! Every connection is served on its own thread, speaking the dialect of the
+//! first method it calls. Jobs are handed to the server with
+//! [`StratumServer::notify`] and pushed to all the authorized workers, and
+//! the shares found valid, checked with [`LightDAG::hashimoto`] against the
+//! share boundary of their worker, are sent down the channel returned by
+//! [`StratumServer::bind`].
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use ethereum_types::{BigEndianHash, H256, H64, U256};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+
+use super::{
+    boundary_hex, h256_param, h64_param, to_hex, to_stratum_difficulty,
+    Dialect, ShareError, STRATUM_VERSION,
+};
+use crate::chain::ChainConfig;
+use crate::LightDAG;
+
+// Longest request line, newline included. A longer one closes the session,
+// so that a client cannot have the server buffer without bound.
+const MAX_LINE: u64 = 16 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Chain the jobs are blocks of, which sets the lengths of their epochs.
+    pub chain: ChainConfig,
+    /// Network difficulty of the shares of workers without a difficulty of
+    /// their own.
+    pub difficulty: U256,
+    /// Number of leading nonce bytes assigned to every EthereumStratum
+    /// connection. Subscriptions are refused while all of the `256^size`
+    /// extranonces are taken.
+    pub extranonce_size: usize,
+    /// Number of recent jobs shares are accepted for.
+    pub max_jobs: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            chain: ChainConfig::default(),
+            difficulty: U256::from(1u64 << 32),
+            extranonce_size: 2,
+            max_jobs: 8,
+        }
+    }
+}
+
+/// A block to be mined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub id: String,
+    /// Hash of the header without the seal.
+    pub header_hash: H256,
+    /// Number of the block.
+    pub number: u64,
+    /// Network difficulty of the block.
+    pub difficulty: U256,
+}
+
+/// A valid share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    pub worker: String,
+    pub job_id: String,
+    pub header_hash: H256,
+    pub nonce: H64,
+    pub mix_hash: H256,
+    /// Final hash of the seal, compared against the boundaries.
+    pub hash: H256,
+    /// Whether the share meets the difficulty of the block.
+    pub is_block: bool,
+}
+
+struct JobEntry {
+    job: Job,
+    // Index of the seed hash of the job, which keys its DAG.
+    seed_epoch: usize,
+    seed_hash: H256,
+    // Nonces of the shares accepted so far.
+    submitted: HashSet<H64>,
+}
+
+struct Session {
+    dialect: Option<Dialect>,
+    extranonce: Option<Vec<u8>>,
+    worker: Option<String>,
+    difficulty: U256,
+    writer: Writer,
+}
+
+/// Write end of a connection, locked to write whole messages.
+type Writer = Arc<Mutex<TcpStream>>;
+
+/// Messages to write once the server state is unlocked, so that a slow
+/// connection does not hold up the others.
+type Outbox = Vec<(Writer, Value)>;
+
+fn send(writer: &Writer, message: &Value) {
+    // a broken connection is dropped by its reading thread.
+    let _ = writeln!(writer.lock(), "{}", message);
+}
+
+fn send_all(outbox: Outbox) {
+    for (writer, message) in outbox {
+        send(&writer, &message);
+    }
+}
+
+/// A share that passed the checks done with the server state locked, and
+/// remains to be hashed.
+struct Submission {
+    dag: Arc<LightDAG>,
+    job: Job,
+    worker: String,
+    nonce: H64,
+    mix_hash: Option<H256>,
+    difficulty: U256,
+}
+
+struct State {
+    config: ServerConfig,
+    jobs: VecDeque<JobEntry>,
+    // DAGs by seed epoch.
+    dags: HashMap<usize, Arc<LightDAG>>,
+    sessions: HashMap<u64, Session>,
+    next_session: u64,
+    // Extranonces released by closed sessions, and the next one never
+    // handed out.
+    free_extranonces: Vec<Vec<u8>>,
+    next_extranonce: u64,
+    worker_difficulty: HashMap<String, U256>,
+    shares: Sender<Share>,
+}
+
+/// A stratum server accepting connections in the background.
+pub struct StratumServer {
+    state: Arc<Mutex<State>>,
+    local_addr: SocketAddr,
+    chain: ChainConfig,
+}
+
+impl StratumServer {
+    /// Listen on `addr`, returning the server and the channel of the valid
+    /// shares.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        config: ServerConfig,
+    ) -> io::Result<(Self, Receiver<Share>)> {
+        assert!(config.extranonce_size < 8, "extranonce leaves no nonce");
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let (shares, receiver) = mpsc::channel();
+        let chain = config.chain.clone();
+        let state = Arc::new(Mutex::new(State {
+            config,
+            jobs: VecDeque::new(),
+            dags: HashMap::new(),
+            sessions: HashMap::new(),
+            next_session: 0,
+            free_extranonces: Vec::new(),
+            next_extranonce: 0,
+            worker_difficulty: HashMap::new(),
+            shares,
+        }));
+
+        let accepting = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = accepting.clone();
+                thread::spawn(move || serve(&state, stream));
+            }
+        });
+        let server = Self {
+            state,
+            local_addr,
+            chain,
+        };
+        Ok((server, receiver))
+    }
+
+    pub fn local_addr(&self) -> SocketAddr { self.local_addr }
+
+    /// Use `dag` to validate the shares of its epoch, instead of building it
+    /// when a job of the epoch is notified.
+    pub fn insert_dag(&self, dag: LightDAG) {
+        let seed_epoch = dag.seed_epoch();
+        self.state.lock().dags.insert(seed_epoch, Arc::new(dag));
+    }
+
+    /// Hand a new job to the workers. With `clean`, the shares of the
+    /// previous jobs are rejected as stale from now on.
+    ///
+    /// Blocks while the cache of a new epoch is built.
+    pub fn notify(&self, job: Job, clean: bool) {
+        let seed_epoch = self.chain.seed_epoch(job.number);
+        let has_dag = self.state.lock().dags.contains_key(&seed_epoch);
+        let dag =
+            (!has_dag).then(|| Arc::new(self.chain.light_dag(job.number)));
+
+        let mut state = self.state.lock();
+        if let Some(dag) = dag {
+            state.dags.insert(seed_epoch, dag);
+        }
+        if clean {
+            state.jobs.clear();
+        }
+        state.jobs.push_back(JobEntry {
+            seed_epoch,
+            seed_hash: crate::get_seedhash(seed_epoch),
+            job,
+            submitted: HashSet::new(),
+        });
+        while state.jobs.len() > state.config.max_jobs {
+            state.jobs.pop_front();
+        }
+        let epochs: HashSet<usize> =
+            state.jobs.iter().map(|entry| entry.seed_epoch).collect();
+        state.dags.retain(|epoch, _| epochs.contains(epoch));
+
+        let entry = state.jobs.back().expect("job was just pushed");
+        let outbox: Outbox = state
+            .sessions
+            .values()
+            .filter(|session| session.worker.is_some())
+            .map(|session| {
+                let message = work_message(session, entry, clean);
+                (session.writer.clone(), message)
+            })
+            .collect();
+        drop(state);
+        send_all(outbox);
+    }
+
+    /// Set the share difficulty of `worker`, for its current and future
+    /// connections.
+    pub fn set_difficulty(&self, worker: &str, difficulty: U256) {
+        let mut state = self.state.lock();
+        state.worker_difficulty.insert(worker.into(), difficulty);
+        let State { jobs, sessions, .. } = &mut *state;
+        let mut outbox = Outbox::new();
+        for session in sessions.values_mut() {
+            if session.worker.as_deref() != Some(worker) {
+                continue;
+            }
+            session.difficulty = difficulty;
+            let message = match session.dialect {
+                Some(Dialect::EthereumStratum) => {
+                    difficulty_message(difficulty)
+                },
+                // the boundary is part of the work.
+                _ => match jobs.back() {
+                    Some(entry) => work_message(session, entry, false),
+                    None => continue,
+                },
+            };
+            outbox.push((session.writer.clone(), message));
+        }
+        drop(state);
+        send_all(outbox);
+    }
+}
+
+fn serve(state: &Mutex<State>, stream: TcpStream) {
+    let writer = match stream.try_clone() {
+        Ok(writer) => Arc::new(Mutex::new(writer)),
+        Err(_) => return,
+    };
+    let id = {
+        let mut state = state.lock();
+        let id = state.next_session;
+        state.next_session += 1;
+        let difficulty = state.config.difficulty;
+        state.sessions.insert(id, Session {
+            dialect: None,
+            extranonce: None,
+            worker: None,
+            difficulty,
+            writer: writer.clone(),
+        });
+        id
+    };
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match (&mut reader).take(MAX_LINE).read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(n) if n as u64 == MAX_LINE && !line.ends_with('\n') => break,
+            Ok(_) => {},
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(_) => break,
+        };
+        for reply in handle(state, id, &request) {
+            send(&writer, &reply);
+        }
+    }
+    let mut state = state.lock();
+    let session = state.sessions.remove(&id).expect("session is open");
+    if let Some(extranonce) = session.extranonce {
+        state.free_extranonces.push(extranonce);
+    }
+}
+
+/// Handle a request of the session `id`, returning the messages to send
+/// back.
+fn handle(state: &Mutex<State>, id: u64, request: &Value) -> Vec<Value> {
+    let method = request["method"].as_str().unwrap_or_default();
+    let params = &request["params"];
+    let dialect = if method.starts_with("eth_") {
+        Dialect::EthProxy
+    } else {
+        Dialect::EthereumStratum
+    };
+    let reply = |result: Result<Value, ShareError>| {
+        vec![response(dialect, &request["id"], result)]
+    };
+
+    match method {
+        "mining.subscribe" => {
+            let mut state = state.lock();
+            // subscribing again keeps the extranonce.
+            let extranonce = match state.sessions[&id].extranonce.clone() {
+                Some(extranonce) => extranonce,
+                None => match state.allocate_extranonce() {
+                    Some(extranonce) => extranonce,
+                    None => return reply(Err(ShareError::NoExtranonce)),
+                },
+            };
+            let session = state.sessions.get_mut(&id).expect("session");
+            session.dialect = Some(dialect);
+            let subscription = to_hex(&id.to_be_bytes(), false);
+            let result = json!([
+                ["mining.notify", subscription, STRATUM_VERSION],
+                to_hex(&extranonce, false),
+            ]);
+            session.extranonce = Some(extranonce);
+            reply(Ok(result))
+        },
+        "mining.extranonce.subscribe" => reply(Ok(Value::Bool(true))),
+        "mining.authorize" => {
+            let worker = match params[0].as_str() {
+                Some(worker) => worker,
+                None => return reply(Err(ShareError::Malformed)),
+            };
+            let mut state = state.lock();
+            let difficulty = state.difficulty_of(worker);
+            let State { jobs, sessions, .. } = &mut *state;
+            let session = sessions.get_mut(&id).expect("session");
+            if session.extranonce.is_none() {
+                return reply(Err(ShareError::NotSubscribed));
+            }
+            session.worker = Some(worker.into());
+            session.difficulty = difficulty;
+            let mut replies = reply(Ok(Value::Bool(true)));
+            replies.push(difficulty_message(difficulty));
+            if let Some(entry) = jobs.back() {
+                replies.push(work_message(session, entry, true));
+            }
+            replies
+        },
+        "mining.submit" => {
+            let submission = state.lock().stratum_submission(id, params);
+            reply(submission.and_then(|s| validate(state, s)).map(Value::Bool))
+        },
+        "eth_submitLogin" => {
+            let login = match params[0].as_str() {
+                Some(login) => login,
+                None => return reply(Err(ShareError::Malformed)),
+            };
+            let worker = match request["worker"].as_str() {
+                Some(name) if !name.is_empty() => format!("{}.{}", login, name),
+                _ => login.into(),
+            };
+            let mut state = state.lock();
+            let difficulty = state.difficulty_of(&worker);
+            let session = state.sessions.get_mut(&id).expect("session");
+            session.dialect = Some(dialect);
+            session.worker = Some(worker);
+            session.difficulty = difficulty;
+            reply(Ok(Value::Bool(true)))
+        },
+        "eth_getWork" => {
+            let state = state.lock();
+            let session = &state.sessions[&id];
+            if session.worker.is_none() {
+                return reply(Err(ShareError::Unauthorized));
+            }
+            match state.jobs.back() {
+                Some(entry) => reply(Ok(work(session, entry))),
+                None => reply(Err(ShareError::Stale)),
+            }
+        },
+        "eth_submitWork" => {
+            let submission = state.lock().proxy_submission(id, params);
+            reply(submission.and_then(|s| validate(state, s)).map(Value::Bool))
+        },
+        "eth_submitHashrate" => reply(Ok(Value::Bool(true))),
+        _ => reply(Err(ShareError::Malformed)),
+    }
+}
+
+impl State {
+    /// An extranonce no open session has, `None` once they are all taken.
+    fn allocate_extranonce(&mut self) -> Option<Vec<u8>> {
+        if let Some(extranonce) = self.free_extranonces.pop() {
+            return Some(extranonce);
+        }
+        let size = self.config.extranonce_size;
+        if self.next_extranonce >> (8 * size) != 0 {
+            return None;
+        }
+        let bytes = self.next_extranonce.to_be_bytes();
+        self.next_extranonce += 1;
+        Some(bytes[8 - size..].to_vec())
+    }
+
+    fn difficulty_of(&self, worker: &str) -> U256 {
+        self.worker_difficulty
+            .get(worker)
+            .copied()
+            .unwrap_or(self.config.difficulty)
+    }
+
+    /// `mining.submit` params: worker, job id and the nonce without the
+    /// extranonce.
+    fn stratum_submission(
+        &mut self,
+        id: u64,
+        params: &Value,
+    ) -> Result<Submission, ShareError> {
+        let session = &self.sessions[&id];
+        let extranonce = session
+            .extranonce
+            .as_ref()
+            .ok_or(ShareError::NotSubscribed)?;
+        let worker = session.worker.as_ref().ok_or(ShareError::Unauthorized)?;
+        if params[0].as_str() != Some(worker.as_str()) {
+            return Err(ShareError::Unauthorized);
+        }
+        let job_id = params[1].as_str().ok_or(ShareError::Malformed)?;
+        let suffix = params[2].as_str().ok_or(ShareError::Malformed)?;
+        let suffix = suffix.strip_prefix("0x").unwrap_or(suffix);
+        let mut nonce = extranonce.clone();
+        if suffix.len() != 2 * (8 - nonce.len()) {
+            return Err(ShareError::Malformed);
+        }
+        nonce.resize(8, 0);
+        let start = extranonce.len();
+        hex::decode_to_slice(suffix, &mut nonce[start..])
+            .map_err(|_| ShareError::Malformed)?;
+        let nonce = H64::from_slice(&nonce);
+        self.submission(id, |job| job.id == job_id, nonce, None)
+    }
+
+    /// `eth_submitWork` params: nonce, header hash and mix hash.
+    fn proxy_submission(
+        &mut self,
+        id: u64,
+        params: &Value,
+    ) -> Result<Submission, ShareError> {
+        let nonce = h64_param(&params[0]).ok_or(ShareError::Malformed)?;
+        let header_hash =
+            h256_param(&params[1]).ok_or(ShareError::Malformed)?;
+        let mix_hash = h256_param(&params[2]).ok_or(ShareError::Malformed)?;
+        self.submission(
+            id,
+            |job| job.header_hash == header_hash,
+            nonce,
+            Some(mix_hash),
+        )
+    }
+
+    fn submission<F: Fn(&Job) -> bool>(
+        &mut self,
+        id: u64,
+        is_job: F,
+        nonce: H64,
+        mix_hash: Option<H256>,
+    ) -> Result<Submission, ShareError> {
+        let session = &self.sessions[&id];
+        let worker = session.worker.clone().ok_or(ShareError::Unauthorized)?;
+        let difficulty = session.difficulty;
+        let entry = self
+            .jobs
+            .iter_mut()
+            .find(|entry| is_job(&entry.job))
+            .ok_or(ShareError::Stale)?;
+        if entry.submitted.contains(&nonce) {
+            return Err(ShareError::Duplicate);
+        }
+        let dag = self.dags[&entry.seed_epoch].clone();
+        Ok(Submission {
+            dag,
+            job: entry.job.clone(),
+            worker,
+            nonce,
+            mix_hash,
+            difficulty,
+        })
+    }
+}
+
+/// Hash the submission, without the state locked, and report it if valid.
+fn validate(
+    state: &Mutex<State>,
+    submission: Submission,
+) -> Result<bool, ShareError> {
+    let Submission {
+        dag,
+        job,
+        worker,
+        nonce,
+        mix_hash,
+        difficulty,
+    } = submission;
+    let (mix, hash) = dag.hashimoto(job.header_hash, nonce);
+    if mix_hash.is_some_and(|m| m != mix) {
+        return Err(ShareError::InvalidMixHash);
+    }
+    let value = hash.into_uint();
+    if value > crate::cross_boundary(difficulty) {
+        return Err(ShareError::LowDifficulty);
+    }
+    // only valid shares take their nonce, so that a share rejected for one
+    // worker does not shadow the same nonce found by another.
+    let mut state = state.lock();
+    let entry = state.jobs.iter_mut().find(|entry| {
+        entry.job.id == job.id && entry.job.header_hash == job.header_hash
+    });
+    if let Some(entry) = entry {
+        if !entry.submitted.insert(nonce) {
+            return Err(ShareError::Duplicate);
+        }
+    }
+    let share = Share {
+        worker,
+        job_id: job.id,
+        header_hash: job.header_hash,
+        nonce,
+        mix_hash: mix,
+        hash,
+        is_block: value <= crate::cross_boundary(job.difficulty),
+    };
+    // nobody listening for shares is not an error of the miner.
+    let _ = state.shares.send(share);
+    Ok(true)
+}
+
+fn response(
+    dialect: Dialect,
+    id: &Value,
+    result: Result<Value, ShareError>,
+) -> Value {
+    match (dialect, result) {
+        (Dialect::EthereumStratum, Ok(result)) => {
+            json!({ "id": id, "result": result, "error": null })
+        },
+        (Dialect::EthereumStratum, Err(e)) => json!({
+            "id": id,
+            "result": null,
+            "error": [e.code(), e.message(), null],
+        }),
+        (Dialect::EthProxy, Ok(result)) => {
+            json!({ "id": id, "jsonrpc": "2.0", "result": result })
+        },
+        (Dialect::EthProxy, Err(e)) => json!({
+            "id": id,
+            "jsonrpc": "2.0",
+            "result": null,
+            "error": { "code": e.code(), "message": e.message() },
+        }),
+    }
+}
+
+fn difficulty_message(difficulty: U256) -> Value {
+    json!({
+        "id": null,
+        "method": "mining.set_difficulty",
+        "params": [to_stratum_difficulty(difficulty)],
+    })
+}
+
+/// `eth_getWork` result: header hash, seed hash and share boundary.
+fn work(session: &Session, entry: &JobEntry) -> Value {
+    json!([
+        to_hex(entry.job.header_hash.as_bytes(), true),
+        to_hex(entry.seed_hash.as_bytes(), true),
+        boundary_hex(crate::cross_boundary(session.difficulty)),
+    ])
+}
+
+/// The message pushing the job of `entry` to the session.
+fn work_message(session: &Session, entry: &JobEntry, clean: bool) -> Value {
+    match session.dialect {
+        Some(Dialect::EthereumStratum) => json!({
+            "id": null,
+            "method": "mining.notify",
+            "params": [
+                entry.job.id,
+                to_hex(entry.seed_hash.as_bytes(), false),
+                to_hex(entry.job.header_hash.as_bytes(), false),
+                clean,
+            ],
+        }),
+        _ => json!({
+            "id": 0,
+            "jsonrpc": "2.0",
+            "result": work(session, entry),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn job(id: &str, header_hash: H256) -> Job {
+        Job {
+            id: id.into(),
+            header_hash,
+            number: 0,
+            difficulty: U256::MAX,
+        }
+    }
+
+    /// A miner stand-in, recording the notifications it receives.
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+        notifications: VecDeque<Value>,
+    }
+
+    impl Client {
+        fn connect(server: &StratumServer) -> Self {
+            let stream = TcpStream::connect(server.local_addr()).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(30)))
+                .unwrap();
+            Self {
+                writer: stream.try_clone().unwrap(),
+                reader: BufReader::new(stream),
+                notifications: VecDeque::new(),
+            }
+        }
+
+        fn read(&mut self) -> Value {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        fn call(&mut self, id: u64, method: &str, params: Value) -> Value {
+            let request =
+                json!({ "id": id, "method": method, "params": params });
+            writeln!(self.writer, "{}", request).unwrap();
+            loop {
+                let message = self.read();
+                if message["id"] == id && message.get("method").is_none() {
+                    return message;
+                }
+                self.notifications.push_back(message);
+            }
+        }
+
+        fn submit(&mut self, id: u64, params: [&str; 3]) -> Value {
+            self.call(id, "mining.submit", json!(params))
+        }
+
+        fn notification(&mut self) -> Value {
+            self.notifications.pop_front().unwrap_or_else(|| self.read())
+        }
+    }
+
+    #[test]
+    fn ethereum_stratum() {
+        let config = ServerConfig {
+            difficulty: U256::one(),
+            ..ServerConfig::default()
+        };
+        let (server, shares) =
+            StratumServer::bind("127.0.0.1:0", config).unwrap();
+        let dag = LightDAG::tiny(0);
+        server.insert_dag(LightDAG::tiny(0));
+        server.notify(job("1", H256::repeat_byte(1)), true);
+
+        let mut client = Client::connect(&server);
+        let reply = client.call(1, "mining.authorize", json!(["w", "x"]));
+        assert_eq!(reply["error"][0], 25);
+        let reply =
+            client.call(2, "mining.subscribe", json!(["t", STRATUM_VERSION]));
+        let extranonce = reply["result"][1].as_str().unwrap().to_owned();
+        assert_eq!(extranonce.len(), 4);
+        let reply = client.call(3, "mining.authorize", json!(["w", "x"]));
+        assert_eq!(reply["result"], true);
+        let message = client.notification();
+        assert_eq!(message["method"], "mining.set_difficulty");
+        let message = client.notification();
+        assert_eq!(message["method"], "mining.notify");
+        assert_eq!(message["params"][0], "1");
+        assert_eq!(message["params"][1], to_hex(&[0; 32], false));
+
+        let submit = ["w", "1", "000000000001"];
+        let reply = client.submit(4, submit);
+        assert_eq!(reply["result"], true);
+        let share = shares.try_recv().unwrap();
+        let nonce = format!("{}000000000001", extranonce);
+        assert_eq!(to_hex(share.nonce.as_bytes(), false), nonce);
+        let (mix_hash, hash) = dag.hashimoto(H256::repeat_byte(1), share.nonce);
+        assert_eq!((share.mix_hash, share.hash), (mix_hash, hash));
+        assert!(!share.is_block);
+
+        let reply = client.submit(5, submit);
+        assert_eq!(reply["error"][0], 22);
+        let reply = client.submit(6, ["w", "9", "000000000002"]);
+        assert_eq!(reply["error"][0], 21);
+        let reply = client.submit(7, ["v", "1", "000000000002"]);
+        assert_eq!(reply["error"][0], 24);
+
+        server.notify(job("2", H256::repeat_byte(2)), true);
+        let message = client.notification();
+        assert_eq!(message["params"][0], "2");
+        assert_eq!(message["params"][3], true);
+        let reply = client.submit(8, ["w", "1", "000000000002"]);
+        assert_eq!(reply["error"][0], 21);
+
+        server.set_difficulty("w", U256::MAX);
+        let message = client.notification();
+        assert_eq!(message["method"], "mining.set_difficulty");
+        let reply = client.submit(9, ["w", "2", "000000000003"]);
+        assert_eq!(reply["error"][0], 23);
+        assert!(shares.try_recv().is_err());
+    }
+
+    #[test]
+    fn extranonces() {
+        // a single, empty extranonce.
+        let config = ServerConfig {
+            extranonce_size: 0,
+            ..ServerConfig::default()
+        };
+        let (server, _) = StratumServer::bind("127.0.0.1:0", config).unwrap();
+        let subscribe = |client: &mut Client| {
+            client.call(1, "mining.subscribe", json!(["t", STRATUM_VERSION]))
+        };
+        let mut first = Client::connect(&server);
+        assert_eq!(subscribe(&mut first)["result"][1], "");
+        assert_eq!(subscribe(&mut first)["result"][1], "");
+        let mut second = Client::connect(&server);
+        assert_eq!(subscribe(&mut second)["error"][0], 20);
+
+        // the extranonce is free again once the connection is closed.
+        drop(first);
+        let mut third = Client::connect(&server);
+        for _ in 0..100 {
+            if subscribe(&mut third)["result"][1] == "" {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the extranonce of a closed connection is not released");
+    }
+
+    #[test]
+    fn long_lines() {
+        let config = ServerConfig::default();
+        let (server, _) = StratumServer::bind("127.0.0.1:0", config).unwrap();
+        let mut client = Client::connect(&server);
+        client.writer.write_all(&[b' '; MAX_LINE as usize]).unwrap();
+        // the session is closed without an answer.
+        let mut reply = String::new();
+        match client.reader.read_line(&mut reply) {
+            Ok(n) => assert_eq!(n, 0),
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::ConnectionReset),
+        }
+    }
+
+    #[test]
+    fn eth_proxy() {
+        let config = ServerConfig::default();
+        let (server, shares) =
+            StratumServer::bind("127.0.0.1:0", config).unwrap();
+        let dag = LightDAG::tiny(0);
+        server.insert_dag(LightDAG::tiny(0));
+        let header_hash = H256::repeat_byte(7);
+        let mut first = job("a", header_hash);
+        first.difficulty = U256::one();
+        server.notify(first, true);
+
+        let mut client = Client::connect(&server);
+        let reply = client.call(1, "eth_getWork", json!([]));
+        assert_eq!(reply["error"]["code"], 24);
+        let reply = client.call(2, "eth_submitLogin", json!(["0xabc"]));
+        assert_eq!(reply["result"], true);
+        let reply = client.call(3, "eth_getWork", json!([]));
+        let work = reply["result"].as_array().unwrap();
+        assert_eq!(work[0], to_hex(header_hash.as_bytes(), true));
+        assert_eq!(
+            work[2],
+            boundary_hex(crate::cross_boundary(U256::from(1u64 << 32)))
+        );
+
+        server.set_difficulty("0xabc", U256::one());
+        let message = client.notification();
+        assert_eq!(message["id"], 0);
+        assert_eq!(message["result"][2], boundary_hex(U256::MAX));
+
+        let submit_work = |nonce: u64, mix_hash: H256| {
+            json!([
+                to_hex(&nonce.to_be_bytes(), true),
+                to_hex(header_hash.as_bytes(), true),
+                to_hex(mix_hash.as_bytes(), true),
+            ])
+        };
+        // a rejected share does not take its nonce.
+        let reply =
+            client.call(4, "eth_submitWork", submit_work(6, H256::zero()));
+        assert_eq!(reply["error"]["code"], 20);
+        let nonce = H64::from_low_u64_be(6);
+        let (mix_hash, _) = dag.hashimoto(header_hash, nonce);
+        let reply = client.call(5, "eth_submitWork", submit_work(6, mix_hash));
+        assert_eq!(reply["result"], true);
+        let reply = client.call(6, "eth_submitWork", submit_work(6, mix_hash));
+        assert_eq!(reply["error"]["code"], 22);
+        let share = shares.try_recv().unwrap();
+        assert_eq!((share.worker.as_str(), share.nonce), ("0xabc", nonce));
+        assert!(share.is_block);
+
+        server.notify(job("b", H256::repeat_byte(8)), false);
+        let message = client.notification();
+        assert_eq!(message["result"][0], to_hex(&[8; 32], true));
+    }
+}
@@ -71,13 +71,12 @@ fn proofs() {
     };
     let (depth, leaves) =
         ethash::calc_dataset_merkle_leaves(dag.epoch, &dataset);
-    let leaves: Vec<_> = leaves.iter().collect();
-    let tree = ethash::mtree::MerkleTree::create(&leaves, depth);
+    let tree = ethash::mtree::MerkleTree::create(leaves, depth);
     let root = tree.hash();
 
     // an easier way to calclute the root, if you don't need the proofs.
     // let root = ethash::calc_dataset_merkle_root(dag.epoch, &dataset);
-    println!("root: 0x{}", hex::encode(&root.0));
+    println!("root: 0x{}", hex::encode(root.0));
     assert_eq!(hex::encode(root.0), "f346b91a0469b7960a7b00d7812a5023");
 
     #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -93,7 +92,7 @@ fn proofs() {
     let mut output = BlockWithProofs {
         proof_length: depth as _,
         header_rlp: rlp_encoded_str.trim().to_owned(),
-        merkle_root: hex::encode(&root.0),
+        merkle_root: hex::encode(root.0),
         elements: Vec::with_capacity(depth * 4),
         merkle_proofs: Vec::with_capacity(depth * 2),
     };
@@ -102,10 +101,10 @@ fn proofs() {
         let (element, _leaf_hash, proofs) =
             tree.generate_proof(*index as _, depth);
         let els = element.into_h256_array();
-        output.elements.extend(els.iter().map(|v| hex::encode(&v)));
+        output.elements.extend(els.iter().map(hex::encode));
         output
             .merkle_proofs
-            .extend(proofs.iter().map(|v| hex::encode(&v.0)));
+            .extend(proofs.iter().map(|v| hex::encode(v.0)));
     }
 
     let json = serde_json::to_vec_pretty(&output).unwrap();
@@ -147,11 +146,52 @@ fn proofs() {
             included,
             "index: {}, element: {}",
             index,
-            hex::encode(&element.0)
+            hex::encode(element.0)
         );
     }
 }
 
+// takes about as long as generating the dataset.
+#[test]
+#[ignore]
+fn root_from_cache() {
+    let dag = ethash::LightDAG::new(0.into());
+    let root = ethash::light::calc_dataset_merkle_root_from_cache(
+        dag.epoch, &dag.cache,
+    );
+    assert_eq!(hex::encode(root.0), "f346b91a0469b7960a7b00d7812a5023");
+}
+
+#[test]
+fn abi_roundtrip() {
+    let fixture: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/2.json")).unwrap();
+    let digits = |v: &serde_json::Value| {
+        v.as_str().unwrap().trim_start_matches("0x").to_owned()
+    };
+    // fixed-size values are written without their leading zeros.
+    let fixed = |v: &serde_json::Value, len: usize| {
+        hex::decode(format!("{:0>1$}", digits(v), 2 * len)).unwrap()
+    };
+    let list = |name: &str| fixture[name].as_array().unwrap().iter();
+    let block = types::BlockWithProofs {
+        header_rlp: hex::decode(digits(&fixture["header_rlp"])).unwrap(),
+        merkle_root: Hash::from(fixed(&fixture["merkle_root"], 16).as_slice()),
+        elements: list("elements")
+            .map(|v| H256::from_slice(&fixed(v, 32)))
+            .collect(),
+        merkle_proofs: list("merkle_proofs")
+            .map(|v| Hash::from(fixed(v, 16).as_slice()))
+            .collect(),
+        proof_length: fixture["proof_length"].as_u64().unwrap(),
+    };
+    assert_eq!(block.elements.len(), 64 * 4);
+
+    let abi = block.to_abi();
+    let root = block.merkle_root;
+    assert_eq!(types::BlockWithProofs::from_abi(&abi, root), Ok(block));
+}
+
 #[test]
 fn mix_hash_2() {
     let rlp_encoded_str = include_str!("fixtures/2.rlp");